@@ -0,0 +1,47 @@
+/* C API of root-reader, built with `cargo build -p root-reader-capi`. */
+#ifndef ROOT_READER_H
+#define ROOT_READER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RootReaderFile RootReaderFile;
+
+/* Codes of root_reader_last_error_code. */
+#define ROOT_READER_OK 0
+#define ROOT_READER_INVALID_ARGUMENT 1 /* null or non UTF-8 path, bad index, small buffer */
+#define ROOT_READER_IO 2
+#define ROOT_READER_INVALID_FORMAT 3   /* and other failures of the library */
+#define ROOT_READER_PANIC 4
+
+/* Returns NULL on failure; see root_reader_last_error. */
+RootReaderFile *root_reader_open(const char *path);
+void root_reader_close(RootReaderFile *file);
+
+size_t root_reader_key_count(const RootReaderFile *file);
+/* Returned strings live until root_reader_close; NULL if out of range. */
+const char *root_reader_key_class_name(const RootReaderFile *file, size_t index);
+const char *root_reader_key_name(const RootReaderFile *file, size_t index);
+const char *root_reader_key_title(const RootReaderFile *file, size_t index);
+/* -1 if out of range. */
+int32_t root_reader_key_cycle(const RootReaderFile *file, size_t index);
+int64_t root_reader_key_object_size(const RootReaderFile *file, size_t index);
+
+/* Decompresses the object of the key into buf; returns the byte count or -1.
+   buf may be NULL if len is 0. */
+int64_t root_reader_read_key(const RootReaderFile *file, size_t index, uint8_t *buf, size_t len);
+
+/* Message of the last failure on the calling thread, or NULL. */
+const char *root_reader_last_error(void);
+/* Code of the last failure on the calling thread, or ROOT_READER_OK. */
+int32_t root_reader_last_error_code(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,313 @@
+//! C API over `root_reader::RootFile`, built as a shared and a static
+//! library.
+//!
+//! Keys are addressed by their index in the scan order. Strings returned by
+//! the library stay valid until the file handle is closed, except for the
+//! message of `root_reader_last_error`, which is overwritten by the next
+//! failing call on the same thread. Panics are caught at the boundary and
+//! reported as `ROOT_READER_PANIC`. See `include/root_reader.h`.
+
+use root_reader::{RootFile, RootIoError};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Error codes of `root_reader_last_error_code`, as in the header.
+pub const ROOT_READER_OK: i32 = 0;
+pub const ROOT_READER_INVALID_ARGUMENT: i32 = 1;
+pub const ROOT_READER_IO: i32 = 2;
+pub const ROOT_READER_INVALID_FORMAT: i32 = 3;
+pub const ROOT_READER_PANIC: i32 = 4;
+
+pub struct RootReaderFile {
+    file: RootFile<File>,
+    class_names: Vec<CString>,
+    names: Vec<CString>,
+    titles: Vec<CString>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(i32, CString)>> = const { RefCell::new(None) };
+}
+
+fn set_error(code: i32, message: &str) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((code, to_c_string(message))));
+}
+
+fn set_last_error(err: RootIoError) {
+    let code = match err {
+        RootIoError::IOError(_) | RootIoError::OpenError { .. } => ROOT_READER_IO,
+        _ => ROOT_READER_INVALID_FORMAT,
+    };
+    set_error(code, &err.to_string());
+}
+
+/// Runs `body`, returning `failed` if it panics instead of unwinding into C.
+fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| {
+        set_error(ROOT_READER_PANIC, "root-reader panicked");
+        failed
+    })
+}
+
+fn to_c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+/// Opens the ROOT file at `path`. Returns null on failure.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_open(path: *const c_char) -> *mut RootReaderFile {
+    guard(ptr::null_mut(), || {
+        if path.is_null() {
+            set_error(ROOT_READER_INVALID_ARGUMENT, "path is null");
+            return ptr::null_mut();
+        }
+        let path = match CStr::from_ptr(path).to_str() {
+            Ok(path) => path,
+            Err(_) => {
+                set_error(ROOT_READER_INVALID_ARGUMENT, "path is not valid UTF-8");
+                return ptr::null_mut();
+            }
+        };
+        match RootFile::open(path) {
+            Ok(file) => {
+                let keys = file.keys();
+                let class_names = keys.iter().map(|k| to_c_string(&k.class_name)).collect();
+                let names = keys.iter().map(|k| to_c_string(&k.name)).collect();
+                let titles = keys.iter().map(|k| to_c_string(&k.title)).collect();
+                Box::into_raw(Box::new(RootReaderFile {
+                    file,
+                    class_names,
+                    names,
+                    titles,
+                }))
+            }
+            Err(err) => {
+                set_last_error(err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Closes a handle returned by `root_reader_open`. Null is ignored.
+///
+/// # Safety
+/// `file` must be null or a handle which has not been closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_close(file: *mut RootReaderFile) {
+    guard((), || {
+        if !file.is_null() {
+            drop(Box::from_raw(file));
+        }
+    })
+}
+
+/// Number of keys in the file.
+///
+/// # Safety
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_key_count(file: *const RootReaderFile) -> usize {
+    guard(0, || (*file).file.keys().len())
+}
+
+/// Class name of the key at `index`, or null if out of range.
+///
+/// # Safety
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_key_class_name(
+    file: *const RootReaderFile,
+    index: usize,
+) -> *const c_char {
+    guard(ptr::null(), || {
+        let file = &*file;
+        file.class_names
+            .get(index)
+            .map_or(ptr::null(), |s| s.as_ptr())
+    })
+}
+
+/// Name of the key at `index`, or null if out of range.
+///
+/// # Safety
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_key_name(
+    file: *const RootReaderFile,
+    index: usize,
+) -> *const c_char {
+    guard(ptr::null(), || {
+        let file = &*file;
+        file.names.get(index).map_or(ptr::null(), |s| s.as_ptr())
+    })
+}
+
+/// Title of the key at `index`, or null if out of range.
+///
+/// # Safety
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_key_title(
+    file: *const RootReaderFile,
+    index: usize,
+) -> *const c_char {
+    guard(ptr::null(), || {
+        let file = &*file;
+        file.titles.get(index).map_or(ptr::null(), |s| s.as_ptr())
+    })
+}
+
+/// Cycle number of the key at `index`, or -1 if out of range.
+///
+/// # Safety
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_key_cycle(file: *const RootReaderFile, index: usize) -> i32 {
+    guard(-1, || {
+        (*file)
+            .file
+            .keys()
+            .get(index)
+            .map_or(-1, |k| k.cycle as i32)
+    })
+}
+
+/// Decompressed size of the object stored under the key at `index`, or -1 if
+/// out of range.
+///
+/// # Safety
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_key_object_size(
+    file: *const RootReaderFile,
+    index: usize,
+) -> i64 {
+    guard(-1, || {
+        (*file)
+            .file
+            .keys()
+            .get(index)
+            .map_or(-1, |k| k.obj_len as i64)
+    })
+}
+
+/// Decompresses the object stored under the key at `index` into `buf`.
+///
+/// Returns the number of bytes written, or -1 on failure (including a buffer
+/// smaller than `root_reader_key_object_size`).
+///
+/// # Safety
+/// `file` must be a valid handle and `buf` must be writable for `len` bytes.
+/// `buf` may be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn root_reader_read_key(
+    file: *const RootReaderFile,
+    index: usize,
+    buf: *mut u8,
+    len: usize,
+) -> i64 {
+    guard(-1, || {
+        let file = &(*file).file;
+        let key = match file.keys().get(index) {
+            Some(key) => key,
+            None => {
+                let message = format!("key index {} is out of range", index);
+                set_error(ROOT_READER_INVALID_ARGUMENT, &message);
+                return -1;
+            }
+        };
+        match file.read_object(key) {
+            Ok(data) if data.is_empty() => 0,
+            Ok(data) if data.len() <= len && !buf.is_null() => {
+                ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len());
+                data.len() as i64
+            }
+            Ok(data) => {
+                let message = format!("buffer of {} bytes is too small for {}", len, data.len());
+                set_error(ROOT_READER_INVALID_ARGUMENT, &message);
+                -1
+            }
+            Err(err) => {
+                set_last_error(err);
+                -1
+            }
+        }
+    })
+}
+
+/// Message of the last error on this thread, or null if there was none.
+#[no_mangle]
+pub extern "C" fn root_reader_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |(_, message)| message.as_ptr())
+    })
+}
+
+/// Code of the last error on this thread, or `ROOT_READER_OK` if there was
+/// none.
+#[no_mangle]
+pub extern "C" fn root_reader_last_error_code() -> i32 {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ROOT_READER_OK, |(code, _)| *code)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use root_reader::RootFileWriter;
+
+    #[test]
+    fn read_through_c_api() {
+        let path =
+            std::env::temp_dir().join(format!("root_reader_ffi_{}.root", std::process::id()));
+        let mut writer = RootFileWriter::create(&path).unwrap();
+        writer
+            .write_object("TObjString", "hello", "", b"world")
+            .unwrap();
+        writer.close().unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let file = root_reader_open(c_path.as_ptr());
+            assert!(!file.is_null());
+            assert_eq!(root_reader_key_count(file), 1);
+            assert_eq!(
+                CStr::from_ptr(root_reader_key_name(file, 0)).to_str(),
+                Ok("hello")
+            );
+            assert_eq!(root_reader_key_object_size(file, 0), 5);
+
+            let mut buf = [0u8; 5];
+            assert_eq!(root_reader_read_key(file, 0, buf.as_mut_ptr(), 5), 5);
+            assert_eq!(&buf, b"world");
+            assert_eq!(root_reader_read_key(file, 0, buf.as_mut_ptr(), 4), -1);
+            assert!(!root_reader_last_error().is_null());
+            assert_eq!(root_reader_last_error_code(), ROOT_READER_INVALID_ARGUMENT);
+            assert_eq!(root_reader_read_key(file, 0, ptr::null_mut(), 0), -1);
+            root_reader_close(file);
+
+            let missing = CString::new("no/such/file.root").unwrap();
+            assert!(root_reader_open(missing.as_ptr()).is_null());
+            assert_eq!(root_reader_last_error_code(), ROOT_READER_IO);
+            assert!(root_reader_open(ptr::null()).is_null());
+            assert_eq!(root_reader_last_error_code(), ROOT_READER_INVALID_ARGUMENT);
+            let not_utf8 = CString::new(vec![0xff, 0xfe]).unwrap();
+            assert!(root_reader_open(not_utf8.as_ptr()).is_null());
+            assert_eq!(root_reader_last_error_code(), ROOT_READER_INVALID_ARGUMENT);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
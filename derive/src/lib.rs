@@ -0,0 +1,121 @@
+//! `#[derive(RootClass)]`, re-exported by `root-reader` with the `derive`
+//! feature.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitInt, LitStr};
+
+/// Implements `FromRoot` for a struct whose fields are the data members of
+/// a ROOT class, in the order they are streamed.
+///
+/// The class is named after the struct and has version 1 unless given by
+/// `#[root(class = "...", version = N)]`; members are named after the
+/// fields unless given by `#[root(name = "...")]`. Field types have to
+/// implement `Member`: numbers, `bool`, `String` for `TString`, and fixed
+/// length arrays of these. `RootFile::get` checks the layout against the
+/// streamer info of the file.
+#[proc_macro_derive(RootClass, attributes(root))]
+pub fn derive_root_class(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    let mut class_name = ident.to_string();
+    let mut version = 1u16;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("root")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("class") {
+                class_name = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("version") {
+                version = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else {
+                return Err(meta.error("expected `class` or `version`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "RootClass needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "RootClass can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut names = vec![];
+    let mut idents = vec![];
+    let mut types = vec![];
+    for field in fields {
+        let field_ident = field.ident.as_ref().unwrap();
+        let mut name = field_ident.to_string();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("root")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `name`"))
+                }
+            })?;
+        }
+        names.push(name);
+        idents.push(field_ident);
+        types.push(&field.ty);
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::root_reader::FromRoot for #ident #ty_generics #where_clause {
+            fn class_name() -> ::std::string::String {
+                ::std::string::String::from(#class_name)
+            }
+
+            fn from_root(
+                buf: &mut ::root_reader::ReadBuffer,
+                _: &::root_reader::DecodeContext,
+            ) -> ::std::result::Result<Self, ::root_reader::RootIoError> {
+                let (version, end) = buf.read_version()?;
+                if version != #version {
+                    return Err(::root_reader::RootIoError::Unimplemented(format!(
+                        "Reading {} version {}",
+                        #class_name, version
+                    )));
+                }
+                #(
+                    let #idents = <#types as ::root_reader::Member>::read_member(buf)?;
+                )*
+                buf.skip_to(end)?;
+                Ok(Self { #( #idents ),* })
+            }
+
+            fn streamer_info() -> ::std::option::Option<::root_reader::StreamerInfo> {
+                Some(
+                    ::root_reader::StreamerInfo::new(#class_name, #version as i32)
+                    #(
+                        .with_array(
+                            #names,
+                            <#types as ::root_reader::Member>::ELEMENT_TYPE,
+                            <#types as ::root_reader::Member>::ARRAY_LENGTH,
+                        )
+                    )*
+                )
+            }
+        }
+    })
+}
@@ -0,0 +1,134 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use root_reader::{
+    ElementType, FromRoot, RootClass, RootFile, RootFileWriter, RootIoError, StreamerInfo, ToRoot,
+    WriteBuffer,
+};
+use std::io::Cursor;
+
+#[derive(Debug, PartialEq, RootClass)]
+#[root(class = "Track", version = 2)]
+struct Track {
+    #[root(name = "fPt")]
+    pt: f32,
+    #[root(name = "fCharge")]
+    charge: i32,
+    #[root(name = "fCov")]
+    cov: [f64; 3],
+    #[root(name = "fLabel")]
+    label: String,
+}
+
+impl ToRoot for Track {
+    fn class_name(&self) -> String {
+        "Track".to_string()
+    }
+
+    fn streamer_infos(&self) -> Vec<StreamerInfo> {
+        vec![StreamerInfo::new("Track", 2)
+            .with_member("fPt", ElementType::Float)
+            .with_member("fCharge", ElementType::Int)
+            .with_array("fCov", ElementType::Double, 3)
+            .with_member("fLabel", ElementType::TString)]
+    }
+
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_versioned(2, |buf| {
+            buf.write_f32::<BigEndian>(self.pt)?;
+            buf.write_i32::<BigEndian>(self.charge)?;
+            for value in &self.cov {
+                buf.write_f64::<BigEndian>(*value)?;
+            }
+            buf.write_string(&self.label)
+        })
+    }
+}
+
+#[derive(Debug, RootClass)]
+#[root(class = "Track", version = 2)]
+struct OtherTrack {
+    #[root(name = "fPt")]
+    _pt: f64,
+}
+
+#[test]
+fn decode_derived_class() {
+    let track = Track {
+        pt: 25.0,
+        charge: 1,
+        cov: [1.0, 0.5, 2.0],
+        label: "muon".to_string(),
+    };
+    assert_eq!(
+        Track::streamer_info().unwrap(),
+        track.streamer_infos()[0].clone()
+    );
+
+    let mut writer = RootFileWriter::new(Cursor::new(vec![]), "tracks.root", "").unwrap();
+    writer.write("track", &track).unwrap();
+    let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+    assert_eq!(root.get::<Track>("track").unwrap(), track);
+    assert!(matches!(
+        root.get::<OtherTrack>("track"),
+        Err(RootIoError::StreamerMismatch { version: 2, .. })
+    ));
+}
+
+#[derive(Debug, PartialEq, RootClass)]
+#[root(version = 2)]
+struct TAttLine {
+    #[root(name = "fLineColor")]
+    color: i16,
+    #[root(name = "fLineStyle")]
+    style: i16,
+    #[root(name = "fLineWidth")]
+    width: i16,
+}
+
+impl ToRoot for TAttLine {
+    fn class_name(&self) -> String {
+        "TAttLine".to_string()
+    }
+
+    /// The info as ROOT writes it: members spelled with their typedefs, and
+    /// the checksum ROOT stores for `TAttLine` version 2.
+    fn streamer_infos(&self) -> Vec<StreamerInfo> {
+        let mut info = StreamerInfo::new("TAttLine", 2)
+            .with_member("fLineColor", ElementType::Short)
+            .with_member("fLineStyle", ElementType::Short)
+            .with_member("fLineWidth", ElementType::Short)
+            .with_checksum(2483504457);
+        for (element, type_name) in info
+            .elements
+            .iter_mut()
+            .zip(["Color_t", "Style_t", "Width_t"])
+        {
+            element.type_name = type_name.to_string();
+        }
+        vec![info]
+    }
+
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_versioned(2, |buf| {
+            buf.write_i16::<BigEndian>(self.color)?;
+            buf.write_i16::<BigEndian>(self.style)?;
+            buf.write_i16::<BigEndian>(self.width)?;
+            Ok(())
+        })
+    }
+}
+
+#[test]
+fn match_checksum_stored_by_root() {
+    let line = TAttLine {
+        color: 2,
+        style: 1,
+        width: 3,
+    };
+    assert_eq!(TAttLine::streamer_info().unwrap().checksum(), 2483504457);
+
+    let mut writer = RootFileWriter::new(Cursor::new(vec![]), "line.root", "").unwrap();
+    writer.write("line", &line).unwrap();
+    let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+    assert_eq!(root.streamer_infos().unwrap()[0].checksum(), 2483504457);
+    assert_eq!(root.get::<TAttLine>("line").unwrap(), line);
+}
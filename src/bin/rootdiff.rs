@@ -0,0 +1,162 @@
+//! Compares the keys of two ROOT files.
+//!
+//! Usage: `rootdiff [-q] [-t TOLERANCE] FILE1 FILE2`
+//!
+//! The latest cycle of each key is compared, descending into directories:
+//! keys found in one file only, keys of different classes and objects whose
+//! contents differ are reported. Trees and ntuples are compared by their
+//! number of entries only: their branches are not read, so no branch
+//! checksums are compared. The bin contents of 1, 2 and 3 dimensional
+//! histograms are compared up to a relative difference of `TOLERANCE`
+//! (1e-6 by default), and other objects byte for byte. Exits with 1 if the
+//! files differ and 2 on errors.
+
+use root_reader::{RootFile, RootIoError, RootKey};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::process::exit;
+
+const USAGE: &str = "Usage: rootdiff [-q] [-t TOLERANCE] FILE1 FILE2";
+
+/// The latest cycle of each of `keys`, by name.
+fn latest(keys: &[RootKey]) -> BTreeMap<&str, &RootKey> {
+    let mut latest = BTreeMap::new();
+    for key in keys {
+        let entry = latest.entry(key.name.as_str()).or_insert(key);
+        if key.cycle > entry.cycle {
+            *entry = key;
+        }
+    }
+    latest
+}
+
+fn within(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs())
+}
+
+struct Diff<'a> {
+    a: &'a RootFile<File>,
+    b: &'a RootFile<File>,
+    tolerance: f64,
+    quiet: bool,
+    differences: usize,
+}
+
+impl Diff<'_> {
+    fn report(&mut self, message: String) {
+        self.differences += 1;
+        if !self.quiet {
+            println!("{}", message);
+        }
+    }
+
+    /// Compares the keys `keys_a` of the first file with `keys_b` of the
+    /// second, in the directory `prefix`.
+    fn keys(
+        &mut self,
+        keys_a: &[RootKey],
+        keys_b: &[RootKey],
+        prefix: &str,
+    ) -> Result<(), RootIoError> {
+        let (latest_a, latest_b) = (latest(keys_a), latest(keys_b));
+        for (name, key_a) in &latest_a {
+            let path = format!("{}{}", prefix, name);
+            match latest_b.get(name) {
+                Some(key_b) => self.key(key_a, key_b, &path)?,
+                None => self.report(format!("only in first: {}", path)),
+            }
+        }
+        for name in latest_b.keys().filter(|name| !latest_a.contains_key(*name)) {
+            self.report(format!("only in second: {}{}", prefix, name));
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, key_a: &RootKey, key_b: &RootKey, path: &str) -> Result<(), RootIoError> {
+        let (a, b) = (self.a, self.b);
+        let class_name = key_a.class_name.as_str();
+        if class_name != key_b.class_name {
+            self.report(format!(
+                "{}: class {} != {}",
+                path, class_name, key_b.class_name
+            ));
+        } else if class_name.starts_with("TDirectory") {
+            // The directory records differ in their seeks and dates.
+            self.keys(
+                &a.directory_keys(key_a)?,
+                &b.directory_keys(key_b)?,
+                &format!("{}/", path),
+            )?;
+        } else if ["TTree", "TNtuple", "TNtupleD"].contains(&class_name) {
+            let (entries_a, entries_b) =
+                (a.tree_totals(key_a)?.entries, b.tree_totals(key_b)?.entries);
+            if entries_a != entries_b {
+                self.report(format!("{}: entries {} != {}", path, entries_a, entries_b));
+            }
+        } else if let (Some(bins_a), Some(bins_b)) =
+            (a.histogram_contents(key_a)?, b.histogram_contents(key_b)?)
+        {
+            let same = bins_a.len() == bins_b.len()
+                && bins_a
+                    .iter()
+                    .zip(&bins_b)
+                    .all(|(&x, &y)| within(x, y, self.tolerance));
+            if !same {
+                self.report(format!("{}: bin contents differ", path));
+            }
+        } else if a.read_object(key_a)? != b.read_object(key_b)? {
+            self.report(format!("{}: contents differ", path));
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let mut quiet = false;
+    let mut tolerance = 1e-6;
+    let mut paths = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" => quiet = true,
+            "-t" => match args.next().and_then(|t| t.parse().ok()) {
+                Some(t) if t >= 0.0 => tolerance = t,
+                _ => {
+                    eprintln!("-t needs a non-negative tolerance");
+                    exit(2);
+                }
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                exit(2);
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+
+    let result = RootFile::open(&paths[0])
+        .and_then(|a| Ok((a, RootFile::open(&paths[1])?)))
+        .and_then(|(a, b)| {
+            let mut diff = Diff {
+                a: &a,
+                b: &b,
+                tolerance,
+                quiet,
+                differences: 0,
+            };
+            diff.keys(a.keys(), b.keys(), "")?;
+            Ok(diff.differences)
+        });
+    match result {
+        Ok(0) => {}
+        Ok(_) => exit(1),
+        Err(err) => {
+            eprintln!("rootdiff: {}", err);
+            exit(2);
+        }
+    }
+}
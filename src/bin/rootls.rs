@@ -0,0 +1,131 @@
+//! Lists the keys of a ROOT file.
+//!
+//! Usage: `rootls [-l] [-r] [-c CLASS] FILE [NAME...]`
+//!
+//! With `-l`, each key is printed with its class, sizes, compression ratio
+//! and algorithm. `-r` descends into directories and prints keys with their
+//! paths like `dir/name`. `-c` keeps only keys of the given class, and names
+//! keep only keys of those names or paths.
+
+use root_reader::{RootFile, RootIoError, RootKey, StreamKind, WalkControl};
+use std::fs::File;
+use std::process::exit;
+
+const USAGE: &str = "Usage: rootls [-l] [-r] [-c CLASS] FILE [NAME...]";
+
+struct Options {
+    long: bool,
+    recursive: bool,
+    class_name: Option<String>,
+    path: String,
+    names: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut long = false;
+        let mut recursive = false;
+        let mut class_name = None;
+        let mut positional = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-l" => long = true,
+                "-r" => recursive = true,
+                "-c" => class_name = Some(args.next().ok_or("-c needs a class name")?),
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+                _ => positional.push(arg),
+            }
+        }
+        if positional.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        let path = positional.remove(0);
+        Ok(Self {
+            long,
+            recursive,
+            class_name,
+            path,
+            names: positional,
+        })
+    }
+
+    fn matches(&self, path: &str, key: &RootKey) -> bool {
+        self.class_name
+            .as_ref()
+            .is_none_or(|c| *c == key.class_name)
+            && (self.names.is_empty()
+                || self
+                    .names
+                    .iter()
+                    .any(|name| *name == key.name || name == path))
+    }
+}
+
+fn print_key(
+    file: &RootFile<File>,
+    path: &str,
+    key: &RootKey,
+    long: bool,
+) -> Result<(), RootIoError> {
+    let name = format!("{};{}", path, key.cycle);
+    if !long {
+        println!("{}", name);
+        return Ok(());
+    }
+    println!(
+        "{:<20} {:<30} {:>12} {:>12} {:>6.2} {:<11} {}",
+        key.class_name,
+        name,
+        key.object_len(),
+        key.stored_len(),
+        key.compression_ratio(),
+        file.stream_kind(key)?.map_or("none", StreamKind::name),
+        key.title
+    );
+    Ok(())
+}
+
+fn list(file: &RootFile<File>, options: &Options) -> Result<(), RootIoError> {
+    if options.long {
+        println!(
+            "{:<20} {:<30} {:>12} {:>12} {:>6} {:<11} TITLE",
+            "CLASS", "NAME", "SIZE", "STORED", "RATIO", "COMPRESSION"
+        );
+    }
+    if !options.recursive {
+        for key in file.keys() {
+            if options.matches(&key.name, key) {
+                print_key(file, &key.name, key, options.long)?;
+            }
+        }
+        return Ok(());
+    }
+    let mut result = Ok(());
+    file.walk(|path, key| {
+        if options.matches(path, key) {
+            result = print_key(file, path, key, options.long);
+        }
+        if result.is_ok() {
+            WalkControl::Continue
+        } else {
+            WalkControl::Stop
+        }
+    })?;
+    result
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(2);
+        }
+    };
+    let result = RootFile::open(&options.path).and_then(|file| list(&file, &options));
+    if let Err(err) = result {
+        eprintln!("rootls: {}", err);
+        exit(1);
+    }
+}
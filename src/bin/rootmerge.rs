@@ -0,0 +1,163 @@
+//! Merges ROOT files, like `hadd`.
+//!
+//! Usage: `rootmerge [-f] [-j JOBS] [-z LEVEL] OUTPUT INPUT...`
+//!
+//! Inputs are opened `JOBS` at a time (as many as cores by default), one
+//! thread each, and merged in the order given, so at most `JOBS` inputs are
+//! open at once. Objects are copied as stored unless `-z` asks for
+//! recompression with zlib (0 stores them uncompressed). The output is
+//! written to a temporary file next to it, which replaces it only once the
+//! merge succeeded; an existing output is only overwritten with `-f`.
+//!
+//! Histograms found in several inputs are added, like `hadd` does, but
+//! trees and profiles are not chained or added: the merge fails on them,
+//! before anything of that input is written. Other objects are taken from
+//! the first input holding their path.
+
+use root_reader::{Compression, RootFile, RootFileWriter, RootIoError};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const USAGE: &str = "Usage: rootmerge [-f] [-j JOBS] [-z LEVEL] OUTPUT INPUT...";
+
+struct Options {
+    force: bool,
+    jobs: usize,
+    compression: Option<Compression>,
+    output: String,
+    inputs: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut force = false;
+        let mut jobs = 0;
+        let mut compression = None;
+        let mut positional = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-f" => force = true,
+                "-j" => {
+                    jobs = args
+                        .next()
+                        .and_then(|j| j.parse().ok())
+                        .ok_or("-j needs a number of jobs")?
+                }
+                "-z" => {
+                    let level = args
+                        .next()
+                        .and_then(|z| z.parse().ok())
+                        .filter(|&level| level <= 9)
+                        .ok_or("-z needs a level from 0 to 9")?;
+                    compression = Some(match level {
+                        0 => Compression::None,
+                        level => Compression::Zlib(level),
+                    });
+                }
+                _ if arg.starts_with('-') => return Err(USAGE.to_string()),
+                _ => positional.push(arg),
+            }
+        }
+        if positional.len() < 2 {
+            return Err(USAGE.to_string());
+        }
+        let output = positional.remove(0);
+        if jobs == 0 {
+            jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+        }
+        Ok(Self {
+            force,
+            jobs,
+            compression,
+            output,
+            inputs: positional,
+        })
+    }
+}
+
+/// Opens `paths` with `jobs` threads, keeping their order.
+fn open_all(paths: &[String], jobs: usize) -> Result<Vec<RootFile<File>>, RootIoError> {
+    let next = AtomicUsize::new(0);
+    let opened: Vec<_> = paths.iter().map(|_| Mutex::new(None)).collect();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(paths.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                match paths.get(i) {
+                    Some(path) => {
+                        *opened[i]
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                            Some(RootFile::open(path))
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+    opened
+        .into_iter()
+        .map(|file| {
+            file.into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .expect("every path is opened")
+        })
+        .collect()
+}
+
+fn merge_into(options: &Options, output: &Path) -> Result<(), RootIoError> {
+    let mut writer = RootFileWriter::create(output)?;
+    if let Some(compression) = options.compression {
+        writer.set_compression(compression);
+    }
+    for paths in options.inputs.chunks(options.jobs) {
+        let inputs = open_all(paths, options.jobs)?;
+        for (path, input) in paths.iter().zip(&inputs) {
+            writer
+                .merge(input, options.compression)
+                .inspect_err(|_| eprintln!("rootmerge: while merging {}", path))?;
+        }
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Merges into a temporary file, which replaces the output on success and
+/// is removed on failure.
+fn merge(options: &Options) -> Result<(), RootIoError> {
+    let temporary = PathBuf::from(format!(
+        "{}.rootmerge-{}.tmp",
+        options.output,
+        std::process::id()
+    ));
+    let result = merge_into(options, &temporary)
+        .and_then(|()| Ok(std::fs::rename(&temporary, &options.output)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(2);
+        }
+    };
+    if !options.force && Path::new(&options.output).exists() {
+        eprintln!(
+            "rootmerge: {} exists, use -f to overwrite it",
+            options.output
+        );
+        exit(1);
+    }
+    if let Err(err) = merge(&options) {
+        eprintln!("rootmerge: {}", err);
+        exit(1);
+    }
+}
@@ -0,0 +1,102 @@
+//! Reports how the space of a ROOT file is used.
+//!
+//! Usage: `rootstat [-h] FILE`
+//!
+//! Prints, per class, the number of keys, their decompressed and stored
+//! sizes, the compression ratio and the algorithms used, followed by the
+//! space taken by key headers and records other than objects. Trees are
+//! counted as whole objects: there are no per-branch, cluster or basket
+//! statistics, as branches and baskets are not decoded.
+
+use root_reader::{RootFile, RootIoError, StreamKind};
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::exit;
+
+const HELP: &str = "Usage: rootstat [-h] FILE
+
+Prints, per class, the number of keys, their decompressed and stored sizes,
+the compression ratio and the algorithms used, followed by the space taken
+by key headers and other records.
+
+Trees are counted as whole objects: there are no per-branch, cluster or
+basket statistics, as branches and baskets are not decoded.";
+
+#[derive(Default)]
+struct Usage {
+    keys: usize,
+    size: u64,
+    stored: u64,
+    algorithms: BTreeSet<&'static str>,
+}
+
+fn ratio(size: u64, stored: u64) -> f64 {
+    size as f64 / stored.max(1) as f64
+}
+
+fn stat(path: &str) -> Result<(), RootIoError> {
+    let file = RootFile::open(path)?;
+    let mut classes: BTreeMap<&str, Usage> = BTreeMap::new();
+    let mut headers = 0;
+    for key in file.keys() {
+        let usage = classes.entry(&key.class_name).or_default();
+        usage.keys += 1;
+        usage.size += key.obj_len as u64;
+        usage.stored += key.stored_len() as u64;
+        usage
+            .algorithms
+            .insert(file.stream_kind(key)?.map_or("none", StreamKind::name));
+        headers += key.key_len as u64;
+    }
+
+    println!(
+        "{:<24} {:>6} {:>14} {:>14} {:>6}  ALGORITHMS",
+        "CLASS", "KEYS", "SIZE", "STORED", "RATIO"
+    );
+    let (mut size, mut stored) = (0, 0);
+    for (class_name, usage) in &classes {
+        size += usage.size;
+        stored += usage.stored;
+        let algorithms: Vec<_> = usage.algorithms.iter().copied().collect();
+        println!(
+            "{:<24} {:>6} {:>14} {:>14} {:>6.2}  {}",
+            class_name,
+            usage.keys,
+            usage.size,
+            usage.stored,
+            ratio(usage.size, usage.stored),
+            algorithms.join(", ")
+        );
+    }
+    println!(
+        "{:<24} {:>6} {:>14} {:>14} {:>6.2}",
+        "total",
+        file.keys().len(),
+        size,
+        stored,
+        ratio(size, stored)
+    );
+    println!();
+    println!("file size     {:>14}", file.end());
+    println!("key headers   {:>14}", headers);
+    println!(
+        "other records {:>14}",
+        file.end().saturating_sub(stored + headers)
+    );
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", HELP);
+        return;
+    }
+    if args.len() != 1 || args[0].starts_with('-') {
+        eprintln!("Usage: rootstat [-h] FILE");
+        exit(2);
+    }
+    if let Err(err) = stat(&args[0]) {
+        eprintln!("rootstat: {}", err);
+        exit(1);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::object::unsupported_class;
+use crate::{RootFile, RootIoError, StreamKind, WalkControl};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+
+/// What a file needs from a reader, as reported by `RootFile::capabilities`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The ROOT version the file was written with, e.g. 62206.
+    pub version: u32,
+    /// Number of keys per compression algorithm, with `None` counting
+    /// uncompressed keys.
+    pub compression: BTreeMap<Option<StreamKind>, usize>,
+    /// Largest version per class in the streamer info of the file.
+    pub class_versions: BTreeMap<String, i32>,
+    /// Everything this build cannot decode, one message each.
+    pub unsupported: Vec<String>,
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// Surveys the keys of the file and of its subdirectories, and its
+    /// streamer info, so datasets can be triaged before they are processed.
+    /// Besides the directories, only the StreamerInfo record and the header
+    /// of the first compression block of each key are read.
+    pub fn capabilities(&self) -> Result<Capabilities, RootIoError> {
+        let mut compression = BTreeMap::new();
+        let mut unsupported = vec![];
+        let mut result = Ok(());
+        self.walk(|path, key| {
+            match self.stream_kind(key) {
+                Ok(kind) => {
+                    *compression.entry(kind).or_insert(0) += 1;
+                    if let Some(kind) = kind.filter(|kind| !kind.is_available()) {
+                        unsupported.push(format!(
+                            "{};{} is compressed with {:?}, whose feature is disabled",
+                            path, key.cycle, kind
+                        ));
+                    }
+                }
+                Err(RootIoError::InvalidFormatError) => unsupported.push(format!(
+                    "{};{} has an unknown compression header",
+                    path, key.cycle
+                )),
+                Err(err) => {
+                    result = Err(err);
+                    return WalkControl::Stop;
+                }
+            }
+            if let Some(err) = unsupported_class(key) {
+                unsupported.push(format!("{} (cycle {})", err, key.cycle));
+            }
+            WalkControl::Continue
+        })?;
+        result?;
+
+        let mut class_versions = BTreeMap::new();
+        match self.streamer_infos() {
+            Ok(infos) => {
+                for info in infos {
+                    let version = class_versions.entry(info.class_name).or_insert(0);
+                    *version = info.class_version.max(*version);
+                }
+            }
+            Err(err @ RootIoError::IOError(_)) => return Err(err),
+            Err(err) => unsupported.push(format!("The streamer info cannot be read: {}", err)),
+        }
+
+        Ok(Capabilities {
+            version: self.version(),
+            compression,
+            class_versions,
+            unsupported,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use crate::{Compression, ElementType, RootFile, RootFileWriter, StreamKind, StreamerInfo};
+
+    #[test]
+    fn report_capabilities() {
+        let mut writer = RootFileWriter::in_memory("survey.root").unwrap();
+        writer.set_compression(Compression::Zlib(1));
+        writer
+            .write_object("TObjString", "a", "", &[1; 1000])
+            .unwrap();
+        writer.set_compression(Compression::None);
+        writer.write_object("TObjString", "b", "", b"x").unwrap();
+        writer
+            .write_object("ROOT::RNTuple", "Events", "", b"")
+            .unwrap();
+        writer.add_streamer_info(StreamerInfo::new("A", 3).with_member("x", ElementType::Int));
+        writer.add_streamer_info(StreamerInfo::new("A", 2).with_member("x", ElementType::Int));
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+
+        let capabilities = root.capabilities().unwrap();
+        assert_eq!(capabilities.version, root.version());
+        assert_eq!(capabilities.compression[&Some(StreamKind::ZlibNew)], 1);
+        assert_eq!(capabilities.compression[&None], 2);
+        assert_eq!(capabilities.class_versions["A"], 3);
+        assert_eq!(capabilities.unsupported.len(), 1);
+        assert!(capabilities.unsupported[0].starts_with("Events is a ROOT::RNTuple"));
+    }
+
+    #[test]
+    fn survey_subdirectories() {
+        let root = RootFile::from_bytes(crate::directory::tests::nested_file()).unwrap();
+        let capabilities = root.capabilities().unwrap();
+        // Three keys at the top, and two in `sub`.
+        assert_eq!(capabilities.compression[&None], 5);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::internal::*;
+use crate::{RootFile, RootIoError, RootKey, VER_THRESHOLD_KEY};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Cursor, Read, Seek, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Size of a directory record; the small layout is padded to the large one.
+pub(crate) const DIRECTORY_LEN: usize = 60;
+
+/// The `TDirectory` record following the name and title of a directory key.
+#[derive(Debug, Clone)]
+pub struct RootDirectory {
+    pub version: u16,
+    pub ctime: u32,
+    pub mtime: u32,
+    pub nbytes_keys: u32,
+    pub nbytes_name: u32,
+    pub seek_dir: u64,
+    pub seek_parent: u64,
+    pub seek_keys: u64,
+    pub uuid: [u8; 18],
+}
+
+impl RootDirectory {
+    pub(crate) fn new(reader: &mut impl Read) -> Result<Self, RootIoError> {
+        read_u16!(reader, version);
+        read_u32!(reader, ctime, mtime, nbytes_keys, nbytes_name);
+        read_u64_val!(
+            version > VER_THRESHOLD_KEY,
+            reader,
+            seek_dir,
+            seek_parent,
+            seek_keys
+        );
+        let mut uuid = [0u8; 18];
+        reader.read_exact(&mut uuid)?;
+        Ok(Self {
+            version,
+            ctime,
+            mtime,
+            nbytes_keys,
+            nbytes_name,
+            seek_dir,
+            seek_parent,
+            seek_keys,
+            uuid,
+        })
+    }
+
+    /// Reads the key headers stored in the key list record of the directory.
+    pub(crate) fn read_keys(
+        &self,
+        reader: &mut (impl Read + Seek),
+        cancel: &AtomicBool,
+        filter: &dyn Fn(&RootKey) -> bool,
+    ) -> Result<Vec<RootKey>, RootIoError> {
+        // Leaves the reader at the start of the list.
+        RootKey::new(reader, self.seek_keys)?;
+        read_u32!(reader, nkeys);
+        let mut keys = Vec::with_capacity(nkeys.min(1 << 16) as usize);
+        for _ in 0..nkeys {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(RootIoError::Cancelled);
+            }
+            let key = RootKey::read_header(reader, false)?;
+            if filter(&key) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Whether the record stores its seeks in 64 bits.
+    pub fn is_large(&self) -> bool {
+        self.version > VER_THRESHOLD_KEY
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> Result<(), RootIoError> {
+        let large = self.is_large();
+        writer.write_u16::<BigEndian>(self.version)?;
+        writer.write_u32::<BigEndian>(self.ctime)?;
+        writer.write_u32::<BigEndian>(self.mtime)?;
+        writer.write_u32::<BigEndian>(self.nbytes_keys)?;
+        writer.write_u32::<BigEndian>(self.nbytes_name)?;
+        write_as_u64(large, writer, self.seek_dir)?;
+        write_as_u64(large, writer, self.seek_parent)?;
+        write_as_u64(large, writer, self.seek_keys)?;
+        writer.write_all(&self.uuid)?;
+        if !large {
+            writer.write_all(&[0; 12])?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// The keys of the subdirectory stored under `key`.
+    pub fn directory_keys(&self, key: &RootKey) -> Result<Vec<RootKey>, RootIoError> {
+        if !key.class_name.starts_with("TDirectory") {
+            return Err(RootIoError::Unimplemented(format!(
+                "Listing the keys of {}",
+                key.class_name
+            )));
+        }
+        let dir = RootDirectory::new(&mut Cursor::new(self.read_object(key)?))?;
+        dir.read_keys(&mut *self.reader(), &AtomicBool::new(false), &|_| true)
+    }
+
+    /// The keys of the subdirectory under `key` inside the directories
+    /// under the keys at `ancestors`, failing if `key` is one of them, or
+    /// the top directory, as the descent of a corrupt file would not end.
+    pub(crate) fn subdirectory_keys(
+        &self,
+        key: &RootKey,
+        ancestors: &[u64],
+    ) -> Result<Vec<RootKey>, RootIoError> {
+        if key.seek_key == self.begin() || ancestors.contains(&key.seek_key) {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        self.directory_keys(key)
+    }
+}
+
+/// What `RootFile::walk` does after visiting a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkControl {
+    Continue,
+    /// Does not descend into the directory just visited.
+    SkipSubtree,
+    Stop,
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// Visits all keys depth-first, descending into subdirectories, with
+    /// their paths from the top directory like `dir/sub/name`. Of a file
+    /// opened by `with_filter`, only the top-level keys kept are visited.
+    pub fn walk(
+        &self,
+        mut visitor: impl FnMut(&str, &RootKey) -> WalkControl,
+    ) -> Result<(), RootIoError> {
+        self.walk_keys(self.keys(), "", &mut vec![], &mut visitor)?;
+        Ok(())
+    }
+
+    // Returns false once the visitor has stopped.
+    fn walk_keys(
+        &self,
+        keys: &[RootKey],
+        prefix: &str,
+        ancestors: &mut Vec<u64>,
+        visitor: &mut dyn FnMut(&str, &RootKey) -> WalkControl,
+    ) -> Result<bool, RootIoError> {
+        for key in keys {
+            let path = format!("{}{}", prefix, key.name);
+            match visitor(&path, key) {
+                WalkControl::Stop => return Ok(false),
+                WalkControl::Continue if key.class_name.starts_with("TDirectory") => {
+                    let keys = self.subdirectory_keys(key, ancestors)?;
+                    ancestors.push(key.seek_key);
+                    let more = self.walk_keys(&keys, &format!("{}/", path), ancestors, visitor)?;
+                    ancestors.pop();
+                    if !more {
+                        return Ok(false);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "find")]
+impl<T: Read + Seek> RootFile<T> {
+    /// All keys in any directory whose name matches the regular expression
+    /// `pattern`, with their paths as given by `walk`.
+    pub fn find(&self, pattern: &str) -> Result<Vec<(String, RootKey)>, RootIoError> {
+        let pattern = regex::Regex::new(pattern)?;
+        let mut found = vec![];
+        self.walk(|path, key| {
+            if pattern.is_match(&key.name) {
+                found.push((path.to_string(), key.clone()));
+            }
+            WalkControl::Continue
+        })?;
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{RootDirectory, WalkControl};
+    use crate::{RootFile, RootFileWriter, RootKey};
+
+    /// A file with `h_pt` at the top and `sub/h_pt` and `sub/h_eta` in a
+    /// subdirectory, whose key list is stored as an object `sub_keys`.
+    /// The record of `sub/h_eta` is freed, so only its key can be used.
+    pub(crate) fn nested_file() -> Vec<u8> {
+        let mut writer = RootFileWriter::in_memory("nested.root").unwrap();
+        writer.write_object("TObjString", "h_pt", "", b"1").unwrap();
+        writer
+            .write_object("TObjString", "h_eta", "", b"2")
+            .unwrap();
+        let inner = writer.keys().to_vec();
+        writer.delete("h_eta", None);
+
+        let mut list = (inner.len() as u32).to_be_bytes().to_vec();
+        for key in &inner {
+            key.write_header(&mut list).unwrap();
+        }
+        writer
+            .write_object("KeysList", "sub_keys", "", &list)
+            .unwrap();
+        let dir = RootDirectory {
+            version: 5,
+            ctime: 0,
+            mtime: 0,
+            nbytes_keys: list.len() as u32,
+            nbytes_name: 0,
+            seek_dir: 0,
+            seek_parent: 100,
+            seek_keys: writer.keys().last().unwrap().seek_key,
+            uuid: [0; 18],
+        };
+        let mut record = vec![];
+        dir.write(&mut record).unwrap();
+        writer
+            .write_object("TDirectoryFile", "sub", "", &record)
+            .unwrap();
+        writer.into_bytes().unwrap()
+    }
+
+    /// A file with a subdirectory `loop` whose key list, stored as an
+    /// object `loop_keys`, holds the key of `loop` itself.
+    pub(crate) fn cyclic_file() -> Vec<u8> {
+        let mut writer = RootFileWriter::in_memory("cyclic.root").unwrap();
+        writer.write_object("TObjString", "a", "", b"1").unwrap();
+        let end = writer.keys()[0].next_position();
+        let mut dir = RootDirectory {
+            version: 5,
+            ctime: 0,
+            mtime: 0,
+            nbytes_keys: 0,
+            nbytes_name: 0,
+            seek_dir: 0,
+            seek_parent: 100,
+            seek_keys: 0,
+            uuid: [0; 18],
+        };
+        let mut record = vec![];
+        dir.write(&mut record).unwrap();
+        let header_len = RootKey::header_len(4, "TDirectoryFile", "loop", "");
+        dir.seek_keys = end + header_len as u64 + record.len() as u64;
+        let mut record = vec![];
+        dir.write(&mut record).unwrap();
+        writer
+            .write_object("TDirectoryFile", "loop", "", &record)
+            .unwrap();
+
+        let mut list = 1u32.to_be_bytes().to_vec();
+        writer.keys()[1].write_header(&mut list).unwrap();
+        writer
+            .write_object("KeysList", "loop_keys", "", &list)
+            .unwrap();
+        assert_eq!(writer.keys()[2].seek_key, dir.seek_keys);
+        writer.into_bytes().unwrap()
+    }
+
+    #[test]
+    fn walk_directories() {
+        let root = RootFile::from_bytes(nested_file()).unwrap();
+        let mut paths = vec![];
+        root.walk(|path, _| {
+            paths.push(path.to_string());
+            WalkControl::Continue
+        })
+        .unwrap();
+        assert_eq!(paths, ["h_pt", "sub_keys", "sub", "sub/h_pt", "sub/h_eta"]);
+
+        let mut paths = vec![];
+        root.walk(|path, _| {
+            paths.push(path.to_string());
+            match path {
+                "sub" => WalkControl::SkipSubtree,
+                _ => WalkControl::Continue,
+            }
+        })
+        .unwrap();
+        assert_eq!(paths.len(), 3);
+
+        let mut count = 0;
+        root.walk(|_, _| {
+            count += 1;
+            WalkControl::Stop
+        })
+        .unwrap();
+        assert_eq!(count, 1);
+
+        let root = RootFile::from_bytes(cyclic_file()).unwrap();
+        assert!(root.walk(|_, _| WalkControl::Continue).is_err());
+    }
+
+    #[cfg(feature = "find")]
+    #[test]
+    fn find_keys() {
+        let root = RootFile::from_bytes(nested_file()).unwrap();
+        let found: Vec<_> = root
+            .find(r"^h_.*t$")
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(found, ["h_pt", "sub/h_pt"]);
+        assert!(root.find("(").is_err());
+    }
+}
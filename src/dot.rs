@@ -0,0 +1,81 @@
+use crate::{RootFile, RootIoError, RootKey};
+use std::fmt::Write;
+use std::io::{Read, Seek};
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// A graphviz graph of the directories and keys of the file, each key
+    /// labelled with its cycle and class.
+    ///
+    /// Only containment is shown; references between objects, such as
+    /// `TRef`s and friend trees, would need the objects to be decoded.
+    pub fn to_dot(&self) -> Result<String, RootIoError> {
+        let mut dot = String::from("digraph root {\n    node [shape=box];\n");
+        dot.push_str("    n0 [label=\"/\", shape=folder];\n");
+        let mut next = 1;
+        self.add_dot_nodes(&mut dot, self.keys(), 0, &mut next, &mut vec![])?;
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    fn add_dot_nodes(
+        &self,
+        dot: &mut String,
+        keys: &[RootKey],
+        parent: usize,
+        next: &mut usize,
+        ancestors: &mut Vec<u64>,
+    ) -> Result<(), RootIoError> {
+        for key in keys {
+            let id = *next;
+            *next += 1;
+            let is_directory = key.class_name.starts_with("TDirectory");
+            // Writing to a `String` does not fail.
+            let _ = writeln!(
+                dot,
+                "    n{} [label=\"{};{}\\n{}\"{}];\n    n{} -> n{};",
+                id,
+                escape(&key.name),
+                key.cycle,
+                escape(&key.class_name),
+                if is_directory { ", shape=folder" } else { "" },
+                parent,
+                id
+            );
+            if is_directory {
+                let keys = self.subdirectory_keys(key, ancestors)?;
+                ancestors.push(key.seek_key);
+                self.add_dot_nodes(dot, &keys, id, next, ancestors)?;
+                ancestors.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use crate::directory::tests::cyclic_file;
+    use crate::tests::small_file;
+    use crate::RootFile;
+
+    #[test]
+    fn export_dot() {
+        let root = RootFile::from_bytes(small_file(&[
+            ("hello", b"world", false),
+            ("say \"hi\"", b"!", false),
+        ]))
+        .unwrap();
+        let dot = root.to_dot().unwrap();
+        assert!(dot.starts_with("digraph root {\n"));
+        assert!(dot.contains("n1 [label=\"hello;1\\nTObj\"];\n    n0 -> n1;\n"));
+        assert!(dot.contains("label=\"say \\\"hi\\\";1"));
+        assert!(dot.ends_with("}\n"));
+
+        let root = RootFile::from_bytes(cyclic_file()).unwrap();
+        assert!(root.to_dot().is_err());
+    }
+}
@@ -1,22 +1,215 @@
 use crate::internal::*;
 use crate::{RootIoError, VER_THRESHOLD_KEY};
-use byteorder::{BigEndian, ReadBytesExt};
-use std::io::{Read, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Seek, SeekFrom, Write};
 
-#[derive(Debug, Eq, PartialEq)]
-pub(crate) enum StreamKind {
-    Uncompressed,
+/// Length of the checksum preceding the data of an LZ4 block.
+#[cfg(feature = "lz4")]
+const LZ4_CHECKSUM_LEN: usize = 8;
+
+/// Compression algorithm of a payload, from the header of its blocks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum StreamKind {
+    /// zlib, as written by ROOT 6.
     ZlibNew,
+    /// Raw deflate, as written by old ROOT versions.
     ZlibOld,
     Lzma,
     Zstd,
+    Lz4,
+}
+
+/// Size of the header of each compression block.
+pub(crate) const HEADER_SIZE: usize = 9;
+
+impl StreamKind {
+    /// Whether this build can decompress the algorithm, i.e. its feature is
+    /// enabled.
+    pub fn is_available(self) -> bool {
+        match self {
+            StreamKind::ZlibNew | StreamKind::ZlibOld => cfg!(feature = "zlib"),
+            StreamKind::Lzma => cfg!(feature = "lzma"),
+            StreamKind::Zstd => cfg!(feature = "zstd"),
+            StreamKind::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// Short name of the algorithm, such as `zlib`.
+    pub fn name(self) -> &'static str {
+        match self {
+            StreamKind::ZlibNew => "zlib",
+            StreamKind::ZlibOld => "zlib (old)",
+            StreamKind::Lzma => "lzma",
+            StreamKind::Zstd => "zstd",
+            StreamKind::Lz4 => "lz4",
+        }
+    }
+
+    pub(crate) fn from_header(header: &[u8]) -> Result<Self, RootIoError> {
+        match &header[..2] {
+            b"ZL" => {
+                if header[2] != 8 {
+                    Err(RootIoError::InvalidFormatError)
+                } else {
+                    Ok(StreamKind::ZlibNew)
+                }
+            }
+            b"CS" => {
+                if header[2] != 8 {
+                    Err(RootIoError::InvalidFormatError)
+                } else {
+                    Ok(StreamKind::ZlibOld)
+                }
+            }
+            b"XZ" => {
+                if header[2] != 0 {
+                    Err(RootIoError::InvalidFormatError)
+                } else {
+                    Ok(StreamKind::Lzma)
+                }
+            }
+            // The method byte carries the library version for these.
+            b"ZS" => Ok(StreamKind::Zstd),
+            b"L4" => Ok(StreamKind::Lz4),
+            _ => Err(RootIoError::InvalidFormatError),
+        }
+    }
+}
+
+/// One compression block of a payload.
+struct Block<'a> {
+    header: &'a [u8],
+    // Unused when built without any decoder.
+    #[cfg_attr(
+        not(any(feature = "zlib", feature = "lzma", feature = "zstd", feature = "lz4")),
+        allow(dead_code)
+    )]
+    content: &'a [u8],
+}
+
+#[cfg(any(feature = "lzma", feature = "zstd", feature = "lz4"))]
+fn invalid_data(err: impl std::fmt::Display) -> RootIoError {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()).into()
+}
+
+/// Splits the first compression block off `raw`, returning it and the
+/// remaining bytes.
+fn split_block(raw: &[u8]) -> Result<(Block<'_>, &[u8]), RootIoError> {
+    if raw.len() < HEADER_SIZE {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    let (header, rest) = raw.split_at(HEADER_SIZE);
+    let len = block_data_len(header);
+    if rest.len() < len {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    let (content, rest) = rest.split_at(len);
+    Ok((Block { header, content }, rest))
+}
+
+/// Length of the compressed data following a block header.
+pub(crate) fn block_data_len(header: &[u8]) -> usize {
+    u32::from_le_bytes([header[3], header[4], header[5], 0]) as usize
+}
+
+/// Length of the decompressed data of a block.
+pub(crate) fn block_obj_len(header: &[u8]) -> usize {
+    u32::from_le_bytes([header[6], header[7], header[8], 0]) as usize
+}
+
+/// Decompresses `raw`, holding exactly one block with its header, which
+/// must decompress to the length given there.
+pub(crate) fn decompress_block(raw: &[u8]) -> Result<Vec<u8>, RootIoError> {
+    let (block, _) = split_block(raw)?;
+    let mut data = vec![];
+    decode_block(block)?.read_to_end(&mut data)?;
+    if data.len() != block_obj_len(raw) {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    Ok(data)
+}
+
+fn decode_block(block: Block<'_>) -> Result<Box<dyn Read + '_>, RootIoError> {
+    let kind = StreamKind::from_header(block.header)?;
+
+    match kind {
+        #[cfg(feature = "zlib")]
+        StreamKind::ZlibNew | StreamKind::ZlibOld => {
+            let zlib_offset = if kind == StreamKind::ZlibNew { 2 } else { 0 };
+            let content = block
+                .content
+                .get(zlib_offset..)
+                .ok_or(RootIoError::InvalidFormatError)?;
+            Ok(Box::new(flate2::read::DeflateDecoder::new(content)))
+        }
+
+        #[cfg(feature = "lzma")]
+        StreamKind::Lzma => {
+            let mut data = Vec::with_capacity(block_obj_len(block.header));
+            lzma_rs::xz_decompress(&mut { block.content }, &mut data).map_err(invalid_data)?;
+            Ok(Box::new(std::io::Cursor::new(data)))
+        }
+
+        #[cfg(feature = "zstd")]
+        StreamKind::Zstd => Ok(Box::new(
+            ruzstd::decoding::StreamingDecoder::new(block.content).map_err(invalid_data)?,
+        )),
+
+        #[cfg(feature = "lz4")]
+        StreamKind::Lz4 => {
+            // The checksum is an xxHash64 of the data, which is not verified.
+            let content = block
+                .content
+                .get(LZ4_CHECKSUM_LEN..)
+                .ok_or(RootIoError::InvalidFormatError)?;
+            let data = lz4_flex::block::decompress(content, block_obj_len(block.header))
+                .map_err(invalid_data)?;
+            Ok(Box::new(std::io::Cursor::new(data)))
+        }
+
+        #[allow(unreachable_patterns)]
+        _ => Err(RootIoError::Unimplemented(format!(
+            "Compression format {:?} without its feature",
+            kind
+        ))),
+    }
 }
 
-const HEADER_SIZE: usize = 9;
+/// A date and time in the packed `TDatime` format of keys and directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Datime {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
 
-impl StreamKind {}
+impl Datime {
+    pub fn from_packed(datime: u32) -> Self {
+        Self {
+            year: (datime >> 26) + 1995,
+            month: (datime >> 22 & 0xf) as u8,
+            day: (datime >> 17 & 0x1f) as u8,
+            hour: (datime >> 12 & 0x1f) as u8,
+            minute: (datime >> 6 & 0x3f) as u8,
+            second: (datime & 0x3f) as u8,
+        }
+    }
+}
 
-#[derive(Debug)]
+impl std::fmt::Display for Datime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RootKey {
     pub begin: u64,
     pub meta_data: Vec<u8>,
@@ -37,35 +230,46 @@ pub struct RootKey {
 impl RootKey {
     pub(crate) fn new(reader: &mut (impl Read + Seek), begin: u64) -> Result<Self, RootIoError> {
         reader.seek(SeekFrom::Start(begin))?;
+        // NOT written in the document.
+        // Use the source. https://root.cern.ch/doc/master/TFile_8cxx_source.html
+        // ROOT bumps the version of keys written beyond `kStartBigFile`, so
+        // the position only matters for keys which got this wrong.
+        let mut key = Self::read_header(reader, begin >= (1u64 << 31))?;
+        if begin != key.seek_key {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        let meta_begin = reader.stream_position()?;
+        if key.obj_begin < meta_begin {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        key.meta_data = vec![0; (key.obj_begin - meta_begin) as usize];
+        reader.read_exact(&mut key.meta_data)?;
+
+        // TODO
+        // parse compression header according to https://github.com/root-project/root/blob/master/js/scripts/JSRoot.io.js#L189
+        Ok(key)
+    }
+
+    /// Parses a key header at the current position, e.g. an entry of a
+    /// directory's key list. `meta_data` is left empty.
+    pub(crate) fn read_header(reader: &mut impl Read, large: bool) -> Result<Self, RootIoError> {
         read_u32!(reader, nbytes);
         read_u16!(reader, version);
         read_u32!(reader, obj_len, datime);
         read_u16!(reader, key_len, cycle);
         read_u64_val!(
-            // NOT written in the document.
-            // Use the source. https://root.cern.ch/doc/master/TFile_8cxx_source.html
-            version > VER_THRESHOLD_KEY || begin >= (1u64 << 31),
+            version > VER_THRESHOLD_KEY || large,
             reader,
             seek_key,
             seek_pdir
         );
-        if begin != seek_key {
-            return Err(RootIoError::InvalidFormatError);
-        }
         let class_name = read_string(reader)?;
         let name = read_string(reader)?;
         let title = read_string(reader)?;
-        let obj_begin = begin + key_len as u64;
-        let meta_begin = reader.seek(SeekFrom::Current(0))?;
-        let mut meta_data = vec![0; (obj_begin - meta_begin) as usize];
-        reader.read_exact(&mut meta_data)?;
-
-        // TODO
-        // parse compression header according to https://github.com/root-project/root/blob/master/js/scripts/JSRoot.io.js#L189
         Ok(Self {
-            begin,
-            meta_data,
-            obj_begin,
+            begin: seek_key,
+            meta_data: vec![],
+            obj_begin: seek_key + key_len as u64,
             nbytes,
             version,
             obj_len,
@@ -80,6 +284,34 @@ impl RootKey {
         })
     }
 
+    /// Whether the key stores its seeks in 64 bits. Files smaller than 2 GB
+    /// use 32 bit keys throughout, as all files written before ROOT 5 did.
+    pub fn is_large(&self) -> bool {
+        self.version > VER_THRESHOLD_KEY
+    }
+
+    pub(crate) fn write_header(&self, writer: &mut impl Write) -> Result<(), RootIoError> {
+        writer.write_u32::<BigEndian>(self.nbytes)?;
+        writer.write_u16::<BigEndian>(self.version)?;
+        writer.write_u32::<BigEndian>(self.obj_len)?;
+        writer.write_u32::<BigEndian>(self.datime)?;
+        writer.write_u16::<BigEndian>(self.key_len)?;
+        writer.write_u16::<BigEndian>(self.cycle)?;
+        write_as_u64(self.is_large(), writer, self.seek_key)?;
+        write_as_u64(self.is_large(), writer, self.seek_pdir)?;
+        write_string(writer, &self.class_name)?;
+        write_string(writer, &self.name)?;
+        write_string(writer, &self.title)?;
+        Ok(())
+    }
+
+    /// Size of the header written by `write_header` for a key
+    /// of the given `version`.
+    pub(crate) fn header_len(version: u16, class_name: &str, name: &str, title: &str) -> u16 {
+        let seeks = if version > VER_THRESHOLD_KEY { 16 } else { 8 };
+        (18 + seeks + string_len(class_name) + string_len(name) + string_len(title)) as u16
+    }
+
     pub(crate) fn next_position(&self) -> u64 {
         self.begin + self.nbytes as u64
     }
@@ -88,86 +320,168 @@ impl RootKey {
         &self,
         reader: &mut (impl Read + Seek),
     ) -> Result<Vec<u8>, RootIoError> {
+        if self.nbytes < self.key_len as u32 {
+            return Err(RootIoError::InvalidFormatError);
+        }
         reader.seek(SeekFrom::Start(self.obj_begin))?;
-        let mut buf = vec![0; self.obj_len as usize];
-        reader.read_exact(&mut buf)?;
+        // Not allocated up front, as a corrupt key can claim up to 4 GB.
+        let len = self.stored_len() as u64;
+        let mut buf = vec![];
+        if reader.take(len).read_to_end(&mut buf)? as u64 != len {
+            return Err(RootIoError::InvalidFormatError);
+        }
         Ok(buf)
     }
 
-    pub(crate) fn detect_stream_kind(
-        &self,
-        reader: &mut (impl Read + Seek),
-    ) -> Result<StreamKind, RootIoError> {
-        reader.seek(SeekFrom::Start(self.obj_begin))?;
+    /// Whether this is the key of a record the file keeps for itself, which
+    /// `TFile::Recover` does not list: the top directory, its key list and
+    /// free segments (all of class `TFile`), the StreamerInfo list, and
+    /// the baskets of trees.
+    pub(crate) fn is_internal(&self) -> bool {
+        matches!(
+            self.class_name.as_str(),
+            "TFile" | "KeysList" | "FreeSegments" | "TBasket"
+        ) || (self.class_name == "TList" && self.name == "StreamerInfo")
+    }
 
-        if self.nbytes == self.obj_len + self.key_len as u32 {
-            return Ok(StreamKind::Uncompressed);
-        }
-        let mut header = [0; HEADER_SIZE];
-        reader.read_exact(&mut header)?;
+    /// Whether the object is stored compressed, i.e. takes less space than
+    /// its length.
+    pub fn is_compressed(&self) -> bool {
+        self.nbytes as u64 != self.obj_len as u64 + self.key_len as u64
+    }
 
-        match &header[..2] {
-            b"ZL" => {
-                if header[2] != 8 {
-                    Err(RootIoError::InvalidFormatError)
-                } else {
-                    Ok(StreamKind::ZlibNew)
-                }
-            }
-            b"CS" => {
-                if header[2] != 8 {
-                    Err(RootIoError::InvalidFormatError)
-                } else {
-                    Ok(StreamKind::ZlibOld)
-                }
-            }
-            b"XZ" => {
-                if header[2] != 0 {
-                    Err(RootIoError::InvalidFormatError)
-                } else {
-                    Ok(StreamKind::Lzma)
-                }
-            }
-            b"ZS" => {
-                if header[2] != 0 {
-                    Err(RootIoError::InvalidFormatError)
-                } else {
-                    Ok(StreamKind::Zstd)
+    /// When the key was written, with no time zone: ROOT writes the local
+    /// time of the writer, and `RootFileWriter` writes UTC.
+    pub fn written_at(&self) -> Datime {
+        Datime::from_packed(self.datime)
+    }
+
+    /// Size of the object payload as stored, without the key header.
+    pub fn stored_len(&self) -> u32 {
+        self.nbytes.saturating_sub(self.key_len as u32)
+    }
+
+    /// Size of the object once decompressed.
+    pub fn object_len(&self) -> u32 {
+        self.obj_len
+    }
+
+    /// `object_len` over `stored_len`; 1 for uncompressed objects.
+    pub fn compression_ratio(&self) -> f64 {
+        self.obj_len as f64 / self.stored_len().max(1) as f64
+    }
+
+    /// Offset of the record of the directory holding the key.
+    pub fn parent_directory(&self) -> u64 {
+        self.seek_pdir
+    }
+
+    /// Describes the first block of the raw payload whose header is not one
+    /// `decompress` knows, if any.
+    pub(crate) fn unknown_block(&self, raw: &[u8]) -> Option<String> {
+        if !self.is_compressed() {
+            return None;
+        }
+        let mut rest = raw;
+        while !rest.is_empty() {
+            let offset = raw.len() - rest.len();
+            match split_block(rest) {
+                Ok((block, next)) if StreamKind::from_header(block.header).is_ok() => rest = next,
+                _ => {
+                    let magic = &rest[..rest.len().min(3)];
+                    return Some(format!(
+                        "Unknown compression header {:?} at offset {}",
+                        String::from_utf8_lossy(magic),
+                        offset
+                    ));
                 }
             }
-            _ => Err(RootIoError::InvalidFormatError),
         }
+        None
     }
 
-    pub(crate) fn decompress<'a>(
-        &'a self,
-        reader: &'a mut (impl Read + Seek),
-    ) -> Result<Box<dyn Read + 'a>, RootIoError> {
-        let kind = self.detect_stream_kind(reader)?;
-
-        match kind {
-            StreamKind::Uncompressed => {
-                reader.seek(SeekFrom::Start(self.obj_begin))?;
-                let result = reader.by_ref();
-                Ok(Box::new(result.take(self.obj_len as u64)))
-            }
-            StreamKind::ZlibNew | StreamKind::ZlibOld => {
-                let zlib_offset: u64 = if kind == StreamKind::ZlibNew { 2 } else { 0 };
-                reader.seek(SeekFrom::Start(
-                    self.obj_begin + HEADER_SIZE as u64 + zlib_offset,
-                ))?;
-                let result = reader.by_ref();
-
-                let content = result.take(
-                    self.nbytes as u64 - self.key_len as u64 - HEADER_SIZE as u64 - zlib_offset,
-                );
-                Ok(Box::new(flate2::read::DeflateDecoder::new(content)))
-            }
+    /// Wraps the raw payload returned by `read_raw_buffer` in a decoder.
+    pub(crate) fn decompress<'a>(&self, raw: &'a [u8]) -> Result<Box<dyn Read + 'a>, RootIoError> {
+        if !self.is_compressed() {
+            return Ok(Box::new(raw));
+        }
 
-            _ => Err(RootIoError::Unimplemented(format!(
-                "Compression format {:?}",
-                kind
-            ))),
+        // Objects larger than one block are compressed in several blocks,
+        // each with its own header.
+        let mut stream: Box<dyn Read + 'a> = Box::new(std::io::empty());
+        let mut rest = raw;
+        while !rest.is_empty() {
+            let (block, next) = split_block(rest)?;
+            stream = Box::new(stream.chain(decode_block(block)?));
+            rest = next;
         }
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Datime;
+
+    #[test]
+    fn unpack_datime() {
+        // 2024-02-29 13:45:07
+        let packed = (29 << 26) | (2 << 22) | (29 << 17) | (13 << 12) | (45 << 6) | 7;
+        assert_eq!(
+            Datime::from_packed(packed).to_string(),
+            "2024-02-29 13:45:07"
+        );
+    }
+
+    #[cfg(all(feature = "lzma", feature = "zstd", feature = "lz4"))]
+    fn block(magic: &[u8; 3], data: &[u8], len: usize) -> Vec<u8> {
+        let mut block = magic.to_vec();
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes()[..3]);
+        block.extend_from_slice(&(len as u32).to_le_bytes()[..3]);
+        block.extend_from_slice(data);
+        block
+    }
+
+    #[cfg(all(feature = "lzma", feature = "zstd", feature = "lz4"))]
+    #[test]
+    fn decompress_all_algorithms() {
+        use super::{RootKey, LZ4_CHECKSUM_LEN};
+        use std::io::Read;
+
+        let payload: Vec<u8> = (0..3000).map(|i| (i % 7) as u8).collect();
+        let mut xz = vec![];
+        lzma_rs::xz_compress(&mut &payload[..1000], &mut xz).unwrap();
+        let zstd = ruzstd::encoding::compress_to_vec(
+            &payload[1000..2000],
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        let mut lz4 = vec![0; LZ4_CHECKSUM_LEN];
+        lz4.extend(lz4_flex::block::compress(&payload[2000..]));
+
+        let mut raw = block(b"XZ\0", &xz, 1000);
+        raw.extend(block(b"ZS\x01", &zstd, 1000));
+        raw.extend(block(b"L4\x01", &lz4, 1000));
+        let key = RootKey {
+            begin: 0,
+            meta_data: vec![],
+            obj_begin: 0,
+            nbytes: raw.len() as u32,
+            version: 4,
+            obj_len: payload.len() as u32,
+            datime: 0,
+            key_len: 0,
+            cycle: 1,
+            seek_key: 0,
+            seek_pdir: 0,
+            class_name: String::new(),
+            name: String::new(),
+            title: String::new(),
+        };
+        let mut data = vec![];
+        key.decompress(&raw)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, payload);
     }
 }
@@ -0,0 +1,96 @@
+use crate::entry::{block_data_len, HEADER_SIZE};
+use crate::{RootFile, RootIoError, RootKey};
+use std::io::{Read, Seek, SeekFrom};
+
+/// What reading a selection of objects would take, from their key headers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadCost {
+    pub objects: usize,
+    /// Bytes fetched from the file.
+    pub stored_bytes: u64,
+    /// Memory taken by the decompressed objects.
+    pub uncompressed_bytes: u64,
+    /// Compression blocks to decompress, each at most 16 MiB when
+    /// decompressed.
+    pub blocks: u64,
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// The cost of reading the objects of `keys`, from their keys and the
+    /// headers of their compression blocks, without decompressing anything.
+    ///
+    /// Branches of a tree are stored in baskets of their own, which this
+    /// crate does not read; for a tree key this is the cost of the tree
+    /// object itself.
+    pub fn estimate<'k>(
+        &self,
+        keys: impl IntoIterator<Item = &'k RootKey>,
+    ) -> Result<ReadCost, RootIoError> {
+        let mut cost = ReadCost::default();
+        for key in keys {
+            cost.objects += 1;
+            cost.stored_bytes += key.stored_len() as u64;
+            cost.uncompressed_bytes += key.obj_len as u64;
+            if key.is_compressed() {
+                cost.blocks += self.count_blocks(key)?;
+            }
+        }
+        Ok(cost)
+    }
+
+    /// Number of compression blocks of the compressed object of `key`, as
+    /// found by following their headers.
+    fn count_blocks(&self, key: &RootKey) -> Result<u64, RootIoError> {
+        let stored = key.stored_len() as u64;
+        let mut reader = self.reader();
+        let mut header = [0; HEADER_SIZE];
+        let (mut offset, mut blocks) = (0, 0);
+        while offset < stored {
+            reader.seek(SeekFrom::Start(key.obj_begin + offset))?;
+            reader.read_exact(&mut header)?;
+            offset += (HEADER_SIZE + block_data_len(&header)) as u64;
+            blocks += 1;
+        }
+        if offset != stored {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use crate::tests::small_file;
+    use crate::{Compression, ReadCost, RootFile, RootFileWriter};
+
+    #[test]
+    fn estimate_read_cost() {
+        let root = RootFile::from_bytes(small_file(&[
+            ("a", &[1; 100], true),
+            ("b", b"plain", false),
+        ]))
+        .unwrap();
+        let cost = root.estimate(root.keys()).unwrap();
+        assert_eq!(cost.objects, 2);
+        assert_eq!(cost.uncompressed_bytes, 105);
+        assert_eq!(
+            cost.stored_bytes,
+            root.keys()
+                .iter()
+                .map(|k| k.stored_len() as u64)
+                .sum::<u64>()
+        );
+        assert_eq!(cost.blocks, 1);
+        assert_eq!(root.estimate(&[]).unwrap(), ReadCost::default());
+
+        let mut writer = RootFileWriter::in_memory("blocks.root").unwrap();
+        writer.set_compression(Compression::Zlib(1));
+        writer.set_block_len(1000);
+        let payload: Vec<u8> = (0..4500u32).map(|i| (i % 251) as u8).collect();
+        writer
+            .write_object("TObjString", "blob", "", &payload)
+            .unwrap();
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+        assert_eq!(root.estimate(root.keys()).unwrap().blocks, 5);
+    }
+}
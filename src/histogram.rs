@@ -0,0 +1,361 @@
+use crate::{ReadBuffer, RootFile, RootIoError, RootKey};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+/// Oldest `TH1` class version whose layout is known here.
+const MIN_TH1_VERSION: u16 = 6;
+
+/// Type of the bins of a histogram, the last letter of its class name.
+#[derive(Debug, Clone, Copy)]
+enum BinType {
+    Char,
+    Short,
+    Int,
+    Long64,
+    Float,
+    Double,
+}
+
+impl BinType {
+    fn size(self) -> usize {
+        match self {
+            BinType::Char => 1,
+            BinType::Short => 2,
+            BinType::Int | BinType::Float => 4,
+            BinType::Long64 | BinType::Double => 8,
+        }
+    }
+
+    fn read(self, data: &[u8]) -> f64 {
+        match self {
+            BinType::Char => data[0] as i8 as f64,
+            BinType::Short => BigEndian::read_i16(data) as f64,
+            BinType::Int => BigEndian::read_i32(data) as f64,
+            BinType::Long64 => BigEndian::read_i64(data) as f64,
+            BinType::Float => BigEndian::read_f32(data) as f64,
+            BinType::Double => BigEndian::read_f64(data),
+        }
+    }
+
+    /// Adds the bin `other` to `bin`, saturating integer bins as
+    /// `TH1I::AddBinContent` does.
+    fn add(self, bin: &mut [u8], other: &[u8]) {
+        match self {
+            BinType::Char => bin[0] = (bin[0] as i8).saturating_add(other[0] as i8) as u8,
+            BinType::Short => {
+                let sum = BigEndian::read_i16(bin).saturating_add(BigEndian::read_i16(other));
+                BigEndian::write_i16(bin, sum)
+            }
+            BinType::Int => {
+                let sum = BigEndian::read_i32(bin).saturating_add(BigEndian::read_i32(other));
+                BigEndian::write_i32(bin, sum)
+            }
+            BinType::Long64 => {
+                let sum = BigEndian::read_i64(bin).saturating_add(BigEndian::read_i64(other));
+                BigEndian::write_i64(bin, sum)
+            }
+            BinType::Float => {
+                let sum = BigEndian::read_f32(bin) + BigEndian::read_f32(other);
+                BigEndian::write_f32(bin, sum)
+            }
+            BinType::Double => add_f64(bin, other),
+        }
+    }
+}
+
+fn add_f64(value: &mut [u8], other: &[u8]) {
+    let sum = BigEndian::read_f64(value) + BigEndian::read_f64(other);
+    BigEndian::write_f64(value, sum)
+}
+
+/// Dimension and bin type of a histogram class like `TH2F`.
+fn histogram_type(class_name: &str) -> Option<(u8, BinType)> {
+    let bin_type = match class_name.as_bytes() {
+        [b'T', b'H', b'1'..=b'3', kind] => match kind {
+            b'C' => BinType::Char,
+            b'S' => BinType::Short,
+            b'I' => BinType::Int,
+            b'L' => BinType::Long64,
+            b'F' => BinType::Float,
+            b'D' => BinType::Double,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((class_name.as_bytes()[2] - b'0', bin_type))
+}
+
+/// Whether `add_histograms` can add objects of `class_name`.
+pub(crate) fn is_histogram(class_name: &str) -> bool {
+    histogram_type(class_name).is_some()
+}
+
+/// Where the members `TH1::Add` sums are in a streamed histogram.
+#[derive(Debug)]
+struct Layout {
+    bin_type: BinType,
+    /// Offsets of the sums of weights (`fEntries`, `fTsumw`, `fTsumwx`...).
+    stats: Vec<usize>,
+    /// The three axes, which have to match for histograms to be added.
+    axes: Range<usize>,
+    /// Offset and length of `fSumw2`.
+    sumw2: (usize, usize),
+    /// Offset and number of the bins, including under- and overflows.
+    bins: (usize, usize),
+}
+
+/// Reads the length of an array of `size` byte values, which has to fit
+/// in the buffer, returning its offset and length.
+fn read_array(
+    buf: &mut ReadBuffer,
+    data: &[u8],
+    size: usize,
+) -> Result<(usize, usize), RootIoError> {
+    let len = buf.read_i32::<BigEndian>()?;
+    let start = buf.position();
+    if len < 0 || len as usize > (data.len() - start) / size {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    buf.skip_to(start + len as usize * size)?;
+    Ok((start, len as usize))
+}
+
+impl Layout {
+    /// The layout of `data`, a histogram of class `class_name`, or `None`
+    /// for other classes. The bins are the `TArray` base, which follows the
+    /// `TH1`, `TH2` or `TH3` base and has no byte count.
+    fn parse(class_name: &str, data: &[u8]) -> Result<Option<Self>, RootIoError> {
+        let (dimension, bin_type) = match histogram_type(class_name) {
+            Some(histogram_type) => histogram_type,
+            None => return Ok(None),
+        };
+        let mut buf = ReadBuffer::new(data, 0);
+        let mut stats = vec![];
+        buf.read_version()?;
+        let (axes, sumw2) = if dimension == 1 {
+            parse_th1(&mut buf, data, &mut stats)?
+        } else {
+            let (_, end) = buf.read_version()?;
+            let th1 = parse_th1(&mut buf, data, &mut stats)?;
+            let sums = if dimension == 2 {
+                // fScalefactor, then fTsumwy, fTsumwy2 and fTsumwxy.
+                buf.read_f64::<BigEndian>()?;
+                3
+            } else {
+                // TAtt3D, then the sums with y and z.
+                let (_, att_end) = buf.read_version()?;
+                buf.skip_to(att_end)?;
+                7
+            };
+            for _ in 0..sums {
+                stats.push(buf.position());
+                buf.read_f64::<BigEndian>()?;
+            }
+            buf.skip_to(end)?;
+            th1
+        };
+        let bins = read_array(&mut buf, data, bin_type.size())?;
+        Ok(Some(Self {
+            bin_type,
+            stats,
+            axes,
+            sumw2,
+            bins,
+        }))
+    }
+
+    fn bin(&self, i: usize) -> Range<usize> {
+        let start = self.bins.0 + i * self.bin_type.size();
+        start..start + self.bin_type.size()
+    }
+}
+
+/// Reads a `TH1` base, returning its axes and `fSumw2`.
+fn parse_th1(
+    buf: &mut ReadBuffer,
+    data: &[u8],
+    stats: &mut Vec<usize>,
+) -> Result<(Range<usize>, (usize, usize)), RootIoError> {
+    let (version, end) = buf.read_version()?;
+    if version < MIN_TH1_VERSION {
+        return Err(RootIoError::Unimplemented(format!(
+            "Reading TH1 version {}",
+            version
+        )));
+    }
+    // TNamed, TAttLine, TAttFill and TAttMarker.
+    for _ in 0..4 {
+        let (_, base_end) = buf.read_version()?;
+        buf.skip_to(base_end)?;
+    }
+    // fNcells.
+    buf.read_i32::<BigEndian>()?;
+    let axes_start = buf.position();
+    for _ in 0..3 {
+        let (_, axis_end) = buf.read_version()?;
+        buf.skip_to(axis_end)?;
+    }
+    let axes = axes_start..buf.position();
+    // fBarOffset and fBarWidth.
+    buf.read_i32::<BigEndian>()?;
+    // fEntries, fTsumw, fTsumw2, fTsumwx and fTsumwx2 are summed, but not
+    // fMaximum, fMinimum and fNormFactor.
+    for i in 0..8 {
+        if i < 5 {
+            stats.push(buf.position());
+        }
+        buf.read_f64::<BigEndian>()?;
+    }
+    // fContour.
+    read_array(buf, data, 8)?;
+    let sumw2 = read_array(buf, data, 8)?;
+    buf.skip_to(end)?;
+    Ok((axes, sumw2))
+}
+
+/// Adds the histogram `other` to `sum`, both streamed objects of class
+/// `class_name`, as `TH1::Add` does: bins, `fSumw2` and the sums of weights
+/// are added in place, so `sum` keeps its length and class tags.
+pub(crate) fn add_histograms(
+    class_name: &str,
+    sum: &mut [u8],
+    other: &[u8],
+) -> Result<(), RootIoError> {
+    let (a, b) = match (
+        Layout::parse(class_name, sum)?,
+        Layout::parse(class_name, other)?,
+    ) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(RootIoError::Unimplemented(format!(
+                "Adding {} objects",
+                class_name
+            )))
+        }
+    };
+    if sum[a.axes.clone()] != other[b.axes.clone()] || a.bins.1 != b.bins.1 {
+        return Err(RootIoError::Unimplemented(format!(
+            "Adding {} histograms of different binnings",
+            class_name
+        )));
+    }
+    if a.sumw2.1 != b.sumw2.1 {
+        return Err(RootIoError::Unimplemented(format!(
+            "Adding {} histograms with and without fSumw2",
+            class_name
+        )));
+    }
+    for (&x, &y) in a.stats.iter().zip(&b.stats) {
+        add_f64(&mut sum[x..x + 8], &other[y..y + 8]);
+    }
+    for i in 0..a.sumw2.1 {
+        let (x, y) = (a.sumw2.0 + 8 * i, b.sumw2.0 + 8 * i);
+        add_f64(&mut sum[x..x + 8], &other[y..y + 8]);
+    }
+    for i in 0..a.bins.1 {
+        let (x, y) = (a.bin(i), b.bin(i));
+        a.bin_type.add(&mut sum[x], &other[y]);
+    }
+    Ok(())
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// The bin contents of the histogram stored under `key`, including the
+    /// under- and overflow bins, or `None` if it is not a `TH1`, `TH2` or
+    /// `TH3` of a basic type, like `TH1F` or `TH2D`.
+    pub fn histogram_contents(&self, key: &RootKey) -> Result<Option<Vec<f64>>, RootIoError> {
+        if !is_histogram(&key.class_name) {
+            return Ok(None);
+        }
+        let data = self.read_object(key)?;
+        let layout = match Layout::parse(&key.class_name, &data)? {
+            Some(layout) => layout,
+            None => return Ok(None),
+        };
+        Ok(Some(
+            (0..layout.bins.1)
+                .map(|i| layout.bin_type.read(&data[layout.bin(i)]))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::add_histograms;
+    use crate::{RootFile, RootFileWriter, WriteBuffer};
+    use byteorder::{BigEndian, WriteBytesExt};
+    use std::io::Write;
+
+    /// A streamed `TH1F` with `bins` (including under- and overflow), the
+    /// given number of entries and `fSumw2`.
+    pub(crate) fn th1f(bins: &[f32], entries: f64, sumw2: &[f64]) -> Vec<u8> {
+        let mut buf = WriteBuffer::new(0);
+        buf.write_versioned(3, |buf| {
+            buf.write_versioned(8, |buf| {
+                buf.write_versioned(1, |buf| {
+                    buf.write_tobject(0)?;
+                    buf.write_string("h")?;
+                    buf.write_string("")
+                })?;
+                for len in [6, 4, 8] {
+                    buf.write_versioned(2, |buf| Ok(buf.write_all(&vec![0; len])?))?;
+                }
+                buf.write_i32::<BigEndian>(bins.len() as i32)?;
+                for axis in [bins.len() as u8 - 2, 1, 1] {
+                    buf.write_versioned(10, |buf| Ok(buf.write_all(&[axis; 12])?))?;
+                }
+                buf.write_i16::<BigEndian>(0)?;
+                buf.write_i16::<BigEndian>(1000)?;
+                let weight: f32 = bins.iter().sum();
+                for stat in [entries, weight as f64, 0.0, 0.0, 0.0, -1111.0, -1111.0, 0.0] {
+                    buf.write_f64::<BigEndian>(stat)?;
+                }
+                buf.write_i32::<BigEndian>(0)?;
+                buf.write_i32::<BigEndian>(sumw2.len() as i32)?;
+                for &w in sumw2 {
+                    buf.write_f64::<BigEndian>(w)?;
+                }
+                // fOption and the rest.
+                buf.write_string("")?;
+                buf.write_all(&[0; 10])?;
+                Ok(())
+            })?;
+            buf.write_i32::<BigEndian>(bins.len() as i32)?;
+            for &bin in bins {
+                buf.write_f32::<BigEndian>(bin)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn add_th1f() {
+        let mut sum = th1f(&[0.0, 1.0, 2.0, 0.5], 3.0, &[0.0, 1.0, 4.0, 0.25]);
+        let other = th1f(&[1.0, 1.0, 1.0, 0.0], 3.0, &[1.0, 1.0, 1.0, 0.0]);
+        add_histograms("TH1F", &mut sum, &other).unwrap();
+        assert_eq!(
+            sum,
+            th1f(&[1.0, 2.0, 3.0, 0.5], 6.0, &[1.0, 2.0, 5.0, 0.25])
+        );
+
+        let wider = th1f(&[0.0; 5], 0.0, &[0.0; 5]);
+        assert!(add_histograms("TH1F", &mut sum, &wider).is_err());
+        let unweighted = th1f(&[0.0; 4], 0.0, &[]);
+        assert!(add_histograms("TH1F", &mut sum, &unweighted).is_err());
+        assert!(add_histograms("TProfile", &mut sum, &other).is_err());
+
+        let mut writer = RootFileWriter::in_memory("hist.root").unwrap();
+        writer.write_object("TH1F", "h", "", &sum).unwrap();
+        writer.write_object("TObjString", "s", "", b"").unwrap();
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+        assert_eq!(
+            root.histogram_contents(&root["h"]).unwrap(),
+            Some(vec![1.0, 2.0, 3.0, 0.5])
+        );
+        assert_eq!(root.histogram_contents(&root["s"]).unwrap(), None);
+    }
+}
@@ -1,5 +1,10 @@
-use byteorder::{BigEndian, ReadBytesExt};
-use std::io::{Read, Seek};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -10,12 +15,47 @@ pub enum RootIoError {
     #[error("{0} is not implemented")]
     Unimplemented(String),
 
+    #[error("Cannot open {}: {source}", path.display())]
+    OpenError {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("No key named {0}")]
+    KeyNotFound(String),
+
+    #[error("{path} holds a {found}, not a {expected}")]
+    TypeMismatch {
+        path: String,
+        found: String,
+        expected: String,
+    },
+
+    #[error("{name} is a {class_name}: {reason}")]
+    UnsupportedClass {
+        name: String,
+        class_name: String,
+        reason: &'static str,
+    },
+
+    #[error("Layout of {class_name} version {version} differs from the file")]
+    StreamerMismatch { class_name: String, version: u16 },
+
+    #[cfg(feature = "find")]
+    #[error(transparent)]
+    InvalidPattern(#[from] regex::Error),
+
+    #[error("Operation was cancelled")]
+    Cancelled,
+
     #[error(transparent)]
     IOError(#[from] std::io::Error),
 }
 
 pub(crate) const VER_THRESHOLD: u32 = 1000000;
 pub(crate) const VER_THRESHOLD_KEY: u16 = 1000;
+/// Offsets beyond this need the 64 bit record layouts (`TFile::kStartBigFile`).
+pub(crate) const START_BIG_FILE: u64 = 2000000000;
 
 pub(crate) fn read_as_u64(cond: bool, reader: &mut impl Read) -> Result<u64, RootIoError> {
     Ok(if cond {
@@ -25,13 +65,60 @@ pub(crate) fn read_as_u64(cond: bool, reader: &mut impl Read) -> Result<u64, Roo
     })
 }
 
+pub(crate) fn write_as_u64(
+    cond: bool,
+    writer: &mut impl Write,
+    value: u64,
+) -> Result<(), RootIoError> {
+    if cond {
+        writer.write_u64::<BigEndian>(value)?;
+    } else {
+        writer.write_u32::<BigEndian>(value as _)?;
+    }
+    Ok(())
+}
+
 pub(crate) fn read_string(reader: &mut impl Read) -> Result<String, RootIoError> {
-    let len = reader.read_u8()?;
+    // Strings longer than 254 bytes are marked by 255 and a 32 bit length.
+    let len = match reader.read_u8()? {
+        255 => reader.read_u32::<BigEndian>()?,
+        len => len as u32,
+    };
     let mut vec = vec![0u8; len as usize];
     reader.read_exact(&mut vec)?;
     Ok(String::from_utf8_lossy(&vec).to_string())
 }
 
+/// Splits a ROOT key path like `name;2` into the name and the cycle, if any.
+pub(crate) fn split_cycle(path: &str) -> (&str, Option<u16>) {
+    match path.rsplit_once(';') {
+        Some((name, cycle)) => match cycle.parse() {
+            Ok(cycle) => (name, Some(cycle)),
+            Err(_) => (path, None),
+        },
+        None => (path, None),
+    }
+}
+
+pub(crate) fn write_string(writer: &mut impl Write, s: &str) -> Result<(), RootIoError> {
+    if s.len() < 255 {
+        writer.write_u8(s.len() as u8)?;
+    } else {
+        writer.write_u8(255)?;
+        writer.write_u32::<BigEndian>(s.len() as u32)?;
+    }
+    writer.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn string_len(s: &str) -> usize {
+    if s.len() < 255 {
+        1 + s.len()
+    } else {
+        5 + s.len()
+    }
+}
+
 macro_rules! read_u16 {
     ( $reader: expr, $( $x:ident ),* ) => {
         $(
@@ -56,16 +143,70 @@ macro_rules! read_u64_val {
     };
 }
 
+mod capabilities;
+pub use capabilities::Capabilities;
+mod directory;
+use directory::DIRECTORY_LEN;
+pub use directory::{RootDirectory, WalkControl};
+mod dot;
 mod entry;
-use entry::RootKey;
+mod estimate;
+pub use entry::{Datime, RootKey, StreamKind};
+pub use estimate::ReadCost;
+mod histogram;
+mod object;
+pub use object::{
+    FromRoot, Member, OpaqueObject, ParameterValue, ReadBuffer, TNamed, TObjString, TParameter,
+    ToRoot, WriteBuffer,
+};
+mod pipeline;
+mod registry;
+pub use registry::{ClassRegistry, DecodeContext, Decoded};
+mod stream;
+pub use stream::ObjectReader;
+mod streamer;
+pub use streamer::{ElementType, StreamerCache, StreamerElement, StreamerInfo};
+mod tree;
+pub use tree::{count_entries, TreeTotals};
+mod writer;
+pub use writer::{Compression, RootFileWriter};
+
+#[cfg(feature = "derive")]
+pub use root_reader_derive::RootClass;
 
 pub(crate) mod internal {
-    pub(crate) use super::{read_as_u64, read_string};
+    pub(crate) use super::{read_as_u64, read_string, string_len, write_as_u64, write_string};
+}
+
+/// An object payload read by `RootFile::read_object_lenient`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LenientObject {
+    /// The decompressed payload, or the payload as stored if `warning` is
+    /// set.
+    pub data: Vec<u8>,
+    /// Why the payload was not decompressed.
+    pub warning: Option<String>,
 }
 
+/// The predicate of `RootFile::with_filter`, kept to be applied again by
+/// `refresh`.
+struct KeyFilter(Box<dyn Fn(&RootKey) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for KeyFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("KeyFilter")
+    }
+}
+
+/// A ROOT file opened for reading.
+///
+/// The underlying reader is kept behind a lock which is only held while the
+/// raw bytes of a record are fetched, so a `RootFile` over a `Send` reader is
+/// `Send + Sync` and objects can be read and decompressed from several
+/// threads at once.
 #[derive(Debug)]
 pub struct RootFile<T: Read + Seek> {
-    reader: T,
+    reader: Mutex<T>,
     version: u32,
     begin: u64,
     end: u64,
@@ -78,14 +219,48 @@ pub struct RootFile<T: Read + Seek> {
     seek_info: u64,
     nbytes_info: u32,
     uuid: [u8; 18],
+    directory: Option<RootDirectory>,
     keys: Vec<RootKey>,
+    filter: Option<KeyFilter>,
 }
 
 impl<T: Read + Seek> RootFile<T> {
     pub fn new(reader: T) -> Result<Self, RootIoError> {
+        Self::with_cancel(reader, &AtomicBool::new(false))
+    }
+
+    /// Same as `new`, but the key scan stops with `RootIoError::Cancelled`
+    /// as soon as `cancel` is set from another thread.
+    pub fn with_cancel(reader: T, cancel: &AtomicBool) -> Result<Self, RootIoError> {
+        Self::scan(reader, cancel, &|_| true)
+    }
+
+    /// Same as `new`, but only the keys `filter` accepts are kept, e.g. by
+    /// class or name prefix, which saves memory for files with thousands
+    /// of keys of no interest. `refresh` applies the filter again.
+    ///
+    /// `walk` and `capabilities` only see the top-level keys which were
+    /// kept. `compact` and `RootFileWriter::merge` reject a filtered file,
+    /// as their output would silently lack the other objects.
+    pub fn with_filter(
+        reader: T,
+        filter: impl Fn(&RootKey) -> bool + Send + Sync + 'static,
+    ) -> Result<Self, RootIoError> {
+        let mut file = Self::scan(reader, &AtomicBool::new(false), &filter)?;
+        file.filter = Some(KeyFilter(Box::new(filter)));
+        Ok(file)
+    }
+
+    fn scan(
+        reader: T,
+        cancel: &AtomicBool,
+        filter: &dyn Fn(&RootKey) -> bool,
+    ) -> Result<Self, RootIoError> {
         let mut reader = reader;
         let mut header = [0u8; 4];
 
+        // Seeks in the file are absolute, whatever the reader was left at.
+        reader.seek(SeekFrom::Start(0))?;
         reader.read_exact(&mut header)?;
         if &header != b"root" {
             return Err(RootIoError::InvalidFormatError);
@@ -103,36 +278,46 @@ impl<T: Read + Seek> RootFile<T> {
         let mut uuid = [0u8; 18];
         reader.read_exact(&mut uuid)?;
 
-        let mut pointer = begin;
-        let mut keys = vec![];
-
-        let mut tot_len = 0;
-
-        while pointer < end {
-            let key = RootKey::new(&mut reader, pointer)?;
-            pointer = key.next_position();
-            if key.name == "Particle_size" {
-                dbg!(keys.len());
-                dbg!(&key);
-                tot_len += key.obj_len;
+        let mut directory = None;
+        if nbytes_name > 0 && begin + nbytes_name as u64 + DIRECTORY_LEN as u64 <= end {
+            reader.seek(SeekFrom::Start(begin + nbytes_name as u64))?;
+            let dir = RootDirectory::new(&mut reader)?;
+            if dir.seek_keys > begin && dir.seek_keys < end {
+                directory = Some(dir);
             }
-            keys.push(key);
         }
 
-        dbg!(tot_len / 4);
+        let keys = match &directory {
+            Some(dir) => dir.read_keys(&mut reader, cancel, filter)?,
+            // The file was not closed, so there is no key list. Walk the
+            // records instead, like `TFile::Recover` does.
+            None => {
+                let mut pointer = begin;
+                let mut keys = vec![];
 
-        /*let mut v = vec![];
-        keys[3]
-            .decompress(&mut reader)
-            .unwrap()
-            .read_to_end(&mut v)
-            .unwrap();
-        dbg!(&keys[3]);
-        dbg!(&v[..100]);*/
-        keys = vec![];
+                while pointer < end {
+                    if cancel.load(Ordering::Relaxed) {
+                        return Err(RootIoError::Cancelled);
+                    }
+                    reader.seek(SeekFrom::Start(pointer))?;
+                    let nbytes = reader.read_i32::<BigEndian>()?;
+                    if nbytes < 0 {
+                        // A free segment, marked with its negated length.
+                        pointer += nbytes.unsigned_abs() as u64;
+                        continue;
+                    }
+                    let key = RootKey::new(&mut reader, pointer)?;
+                    pointer = key.next_position();
+                    if !key.is_internal() && filter(&key) {
+                        keys.push(key);
+                    }
+                }
+                keys
+            }
+        };
 
         Ok(RootFile {
-            reader,
+            reader: Mutex::new(reader),
             version,
             begin,
             end,
@@ -145,25 +330,544 @@ impl<T: Read + Seek> RootFile<T> {
             seek_info,
             nbytes_info,
             uuid,
+            directory,
             keys,
+            filter: None,
         })
     }
 
+    /// Re-reads the header and the keys, picking up records another process
+    /// has flushed since the file was opened, e.g. a new autosave cycle of a
+    /// tree being filled. The filter of `with_filter`, if any, is applied
+    /// to the new keys as well. Returns the number of keys which were not
+    /// there before.
+    pub fn refresh(&mut self) -> Result<usize, RootIoError> {
+        let reader = self
+            .reader
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let filter = &self.filter;
+        let fresh = RootFile::scan(reader, &AtomicBool::new(false), &|key| {
+            filter.as_ref().is_none_or(|filter| (filter.0)(key))
+        })?;
+        let old = &self.keys;
+        let added = fresh
+            .keys
+            .iter()
+            .filter(|key| !old.iter().any(|old| old.seek_key == key.seek_key))
+            .count();
+        self.version = fresh.version;
+        self.begin = fresh.begin;
+        self.end = fresh.end;
+        self.seek_free = fresh.seek_free;
+        self.nbytes_free = fresh.nbytes_free;
+        self.nfree = fresh.nfree;
+        self.nbytes_name = fresh.nbytes_name;
+        self.units = fresh.units;
+        self.compress = fresh.compress;
+        self.seek_info = fresh.seek_info;
+        self.nbytes_info = fresh.nbytes_info;
+        self.uuid = fresh.uuid;
+        self.directory = fresh.directory;
+        self.keys = fresh.keys;
+        Ok(added)
+    }
+
+    /// Whether the file was opened by `with_filter`, so `keys` may lack some
+    /// of its keys.
+    pub fn is_filtered(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// Whether the header stores its seeks in 64 bits, as files written by
+    /// ROOT 6 do. Older files only have 32 bit seeks, in the header as well as
+    /// in keys and directories; see `RootKey::is_large`.
     pub fn is_large_file(&self) -> bool {
         self.version >= VER_THRESHOLD
     }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn begin(&self) -> u64 {
+        self.begin
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn seek_free(&self) -> u64 {
+        self.seek_free
+    }
+
+    pub fn nbytes_free(&self) -> u32 {
+        self.nbytes_free
+    }
+
+    pub fn nfree(&self) -> u32 {
+        self.nfree
+    }
+
+    pub fn nbytes_name(&self) -> u32 {
+        self.nbytes_name
+    }
+
+    pub fn units(&self) -> u8 {
+        self.units
+    }
+
+    pub fn compress(&self) -> u32 {
+        self.compress
+    }
+
+    pub fn seek_info(&self) -> u64 {
+        self.seek_info
+    }
+
+    pub fn nbytes_info(&self) -> u32 {
+        self.nbytes_info
+    }
+
+    pub fn uuid(&self) -> &[u8; 18] {
+        &self.uuid
+    }
+
+    /// The top directory record, or `None` if the file was not closed
+    /// properly and its keys had to be recovered.
+    pub fn directory(&self) -> Option<&RootDirectory> {
+        self.directory.as_ref()
+    }
+
+    pub fn keys(&self) -> &[RootKey] {
+        &self.keys
+    }
+
+    /// The key `path`, given as `name` for the latest cycle or as
+    /// `name;cycle`. Autosaved trees leave older cycles of their key behind,
+    /// which hold snapshots with fewer entries; see `cycles`.
+    pub fn key(&self, path: &str) -> Result<&RootKey, RootIoError> {
+        let (name, cycle) = split_cycle(path);
+        self.keys
+            .iter()
+            .filter(|k| k.name == name && cycle.is_none_or(|c| k.cycle == c))
+            .max_by_key(|k| k.cycle)
+            .ok_or_else(|| RootIoError::KeyNotFound(path.to_string()))
+    }
+
+    /// All cycles of the key `name`, newest first.
+    pub fn cycles(&self, name: &str) -> Vec<&RootKey> {
+        let mut cycles: Vec<_> = self.keys.iter().filter(|k| k.name == name).collect();
+        cycles.sort_by_key(|k| std::cmp::Reverse(k.cycle));
+        cycles
+    }
+
+    /// Reads the object payload of `key` as stored on disk.
+    pub fn read_raw_object(&self, key: &RootKey) -> Result<Vec<u8>, RootIoError> {
+        key.read_raw_buffer(&mut *self.reader())
+    }
+
+    /// The compression of the object of `key`, from its first block header,
+    /// or `None` if it is stored uncompressed.
+    pub fn stream_kind(&self, key: &RootKey) -> Result<Option<StreamKind>, RootIoError> {
+        if !key.is_compressed() {
+            return Ok(None);
+        }
+        let mut header = [0u8; entry::HEADER_SIZE];
+        {
+            let mut reader = self.reader();
+            reader.seek(SeekFrom::Start(key.obj_begin))?;
+            reader.read_exact(&mut header)?;
+        }
+        StreamKind::from_header(&header).map(Some)
+    }
+
+    /// Reads the (decompressed) object payload of `key`.
+    pub fn read_object(&self, key: &RootKey) -> Result<Vec<u8>, RootIoError> {
+        let raw = self.read_raw_object(key)?;
+        let mut buf = Vec::with_capacity(key.obj_len as usize);
+        key.decompress(&raw)?.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Same as `read_object`, but a payload with a compression header this
+    /// crate does not know, e.g. from an exotic or legacy setting, is
+    /// returned as stored along with a warning instead of failing.
+    pub fn read_object_lenient(&self, key: &RootKey) -> Result<LenientObject, RootIoError> {
+        let raw = self.read_raw_object(key)?;
+        if let Some(warning) = key.unknown_block(&raw) {
+            return Ok(LenientObject {
+                data: raw,
+                warning: Some(warning),
+            });
+        }
+        let mut data = Vec::with_capacity(key.obj_len as usize);
+        key.decompress(&raw)?.read_to_end(&mut data)?;
+        Ok(LenientObject {
+            data,
+            warning: None,
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.reader
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Every access seeks before reading, so a reader left behind by a
+    // panicking thread is still usable.
+    fn reader(&self) -> MutexGuard<'_, T> {
+        self.reader
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<'a, T: Read + Seek> IntoIterator for &'a RootFile<T> {
+    type Item = &'a RootKey;
+    type IntoIter = std::slice::Iter<'a, RootKey>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.iter()
+    }
+}
+
+/// `file["name"]` or `file["name;cycle"]`, panicking where `key` would
+/// return an error.
+impl<T: Read + Seek> std::ops::Index<&str> for RootFile<T> {
+    type Output = RootKey;
+
+    fn index(&self, path: &str) -> &RootKey {
+        match self.key(path) {
+            Ok(key) => key,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl RootFile<File> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RootIoError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| RootIoError::OpenError {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::new(file)
+    }
+}
+
+impl RootFile<Cursor<Vec<u8>>> {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, RootIoError> {
+        Self::new(Cursor::new(bytes))
+    }
+
+    /// Opens a forward-only stream such as a pipe or a socket.
+    ///
+    /// ROOT files are laid out for random access (the header points at
+    /// records near the end of the file), so the whole stream is spooled into
+    /// memory before parsing. Memory usage is therefore the size of the file,
+    /// and nothing can be read until the stream has ended.
+    pub fn from_stream(mut reader: impl Read) -> Result<Self, RootIoError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(bytes)
+    }
+}
+
+impl<'a> RootFile<Cursor<&'a [u8]>> {
+    /// Opens a file held in memory, e.g. a memory map, which objects stored
+    /// uncompressed can be borrowed from by `object_bytes`.
+    pub fn from_slice(bytes: &'a [u8]) -> Result<Self, RootIoError> {
+        Self::new(Cursor::new(bytes))
+    }
+
+    /// The object payload of `key`, borrowed from the file if the object is
+    /// stored uncompressed and decompressed into a new buffer otherwise.
+    pub fn object_bytes(&self, key: &RootKey) -> Result<Cow<'a, [u8]>, RootIoError> {
+        if key.is_compressed() {
+            return self.read_object(key).map(Cow::Owned);
+        }
+        let bytes: &'a [u8] = self.reader().get_ref();
+        let start = key.obj_begin as usize;
+        bytes
+            .get(start..start + key.obj_len as usize)
+            .map(Cow::Borrowed)
+            .ok_or(RootIoError::InvalidFormatError)
+    }
 }
 
 #[cfg(test)]
-mod tests {
-    use super::RootFile;
+pub(crate) mod tests {
+    use super::{RootFile, RootFileWriter, RootIoError};
+    #[cfg(feature = "zlib")]
+    use std::borrow::Cow;
+    #[cfg(feature = "zlib")]
+    use std::io::{Cursor, Write};
+    #[cfg(feature = "zlib")]
+    use std::sync::atomic::AtomicBool;
+
+    /// Builds a small-file layout holding one key per `(name, payload, compressed)`,
+    /// without the records written on close, so the keys are recovered by
+    /// scanning.
+    #[cfg(feature = "zlib")]
+    pub(crate) fn small_file(objects: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut keys = vec![];
+        let mut pointer = 100u32;
+        for (name, payload, compressed) in objects {
+            let data = if *compressed {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(payload).unwrap();
+                let zlib = encoder.finish().unwrap();
+                let mut data = b"ZL\x08".to_vec();
+                data.extend_from_slice(&(zlib.len() as u32).to_le_bytes()[..3]);
+                data.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+                data.extend_from_slice(&zlib);
+                data
+            } else {
+                payload.to_vec()
+            };
+            let key_len = 26 + (1 + 4) + (1 + name.len()) + 1;
+            let mut key = vec![];
+            key.extend_from_slice(&((key_len + data.len()) as u32).to_be_bytes());
+            key.extend_from_slice(&4u16.to_be_bytes());
+            key.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            key.extend_from_slice(&0u32.to_be_bytes());
+            key.extend_from_slice(&(key_len as u16).to_be_bytes());
+            key.extend_from_slice(&1u16.to_be_bytes());
+            key.extend_from_slice(&pointer.to_be_bytes());
+            key.extend_from_slice(&100u32.to_be_bytes());
+            for s in &["TObj", name, ""] {
+                key.push(s.len() as u8);
+                key.extend_from_slice(s.as_bytes());
+            }
+            key.extend_from_slice(&data);
+            pointer += key.len() as u32;
+            keys.extend(key);
+        }
+
+        let mut buf = b"root".to_vec();
+        // version, begin, end, seek_free, nbytes_free, nfree, nbytes_name
+        for v in &[62206u32, 100, pointer, 0, 0, 0, 0] {
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        buf.push(4);
+        buf.resize(100, 0);
+        buf.extend(keys);
+        buf
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn read_objects() {
+        let buf = small_file(&[("a", b"plain", false), ("b", &[7; 300], true)]);
+        let root = RootFile::from_bytes(buf).unwrap();
+        assert_eq!(root.keys().len(), 2);
+        assert_eq!(root.read_object(&root.keys()[0]).unwrap(), b"plain");
+        assert_eq!(root.read_object(&root.keys()[1]).unwrap(), vec![7; 300]);
+    }
+
+    #[test]
+    fn find_cycles() {
+        let mut writer = RootFileWriter::in_memory("cycles.root").unwrap();
+        for payload in [&b"1"[..], b"2", b"3"] {
+            writer.write_object("TObjString", "a", "", payload).unwrap();
+        }
+        writer.write_object("TObjString", "b", "", b"").unwrap();
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+
+        assert_eq!(root.key("a").unwrap().cycle, 3);
+        assert_eq!(root.read_object(root.key("a;1").unwrap()).unwrap(), b"1");
+        assert!(matches!(root.key("a;4"), Err(RootIoError::KeyNotFound(_))));
+        let cycles: Vec<_> = root.cycles("a").iter().map(|k| k.cycle).collect();
+        assert_eq!(cycles, [3, 2, 1]);
+        assert_eq!(root["a;2"].cycle, 2);
+        assert_eq!((&root).into_iter().filter(|k| k.name == "a").count(), 3);
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn filter_keys() {
+        let mut writer = RootFileWriter::in_memory("filter.root").unwrap();
+        for name in ["h_pt", "h_eta", "tree"] {
+            writer.write_object("TObjString", name, "", b"").unwrap();
+        }
+        let bytes = writer.into_bytes().unwrap();
+        let root =
+            RootFile::with_filter(Cursor::new(&bytes), |key| key.name.starts_with("h_")).unwrap();
+        let names: Vec<_> = root.keys().iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["h_pt", "h_eta"]);
+
+        let recovered = small_file(&[("a", b"", false), ("b", b"", false)]);
+        let root = RootFile::with_filter(Cursor::new(recovered), |key| key.name == "b").unwrap();
+        assert_eq!(root.keys().len(), 1);
+    }
+
+    #[test]
+    fn recover_user_keys_only() {
+        let mut writer = RootFileWriter::in_memory("recover.root").unwrap();
+        writer.write_object("TObjString", "a", "", b"1").unwrap();
+        let mut bytes = writer.into_bytes().unwrap();
+        // Without nbytes_name, the directory is not found and the records
+        // are walked, which include the TFile key, the StreamerInfo, the
+        // key list and the free segments.
+        bytes[28..32].copy_from_slice(&[0; 4]);
+        let root = RootFile::from_bytes(bytes).unwrap();
+        assert!(root.directory().is_none());
+        let names: Vec<_> = root.keys().iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["a"]);
+    }
+
+    #[test]
+    fn refresh_appended_file() {
+        let path =
+            std::env::temp_dir().join(format!("root_reader_refresh_{}.root", std::process::id()));
+        let mut writer = RootFileWriter::create(&path).unwrap();
+        writer.write_object("TObjString", "a", "", b"1").unwrap();
+        writer.close().unwrap();
+        let mut root = RootFile::open(&path).unwrap();
+        assert_eq!(root.refresh().unwrap(), 0);
+
+        let mut writer = RootFileWriter::open_update(&path).unwrap();
+        writer.write_object("TObjString", "a", "", b"2").unwrap();
+        writer.close().unwrap();
+        assert_eq!(root.refresh().unwrap(), 1);
+        assert_eq!(root.read_object(&root["a"]).unwrap(), b"2");
+
+        let mut root =
+            RootFile::with_filter(std::fs::File::open(&path).unwrap(), |key| key.name == "a")
+                .unwrap();
+        let mut writer = RootFileWriter::open_update(&path).unwrap();
+        writer.write_object("TObjString", "b", "", b"3").unwrap();
+        writer.write_object("TObjString", "a", "", b"4").unwrap();
+        writer.close().unwrap();
+        assert_eq!(root.refresh().unwrap(), 1);
+        assert!(root.keys().iter().all(|key| key.name == "a"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn read_small_files() {
+        // The layout written by ROOT 5.34/34.
+        let mut buf = small_file(&[("a", b"first", false), ("b", b"second", true)]);
+        buf[4..8].copy_from_slice(&53434u32.to_be_bytes());
+        let root = RootFile::from_bytes(buf).unwrap();
+        assert_eq!(root.version(), 53434);
+        assert!(!root.is_large_file());
+        assert!(root.keys().iter().all(|k| !k.is_large()));
+        assert_eq!(root.read_object(&root["b"]).unwrap(), b"second");
+
+        let mut writer = RootFileWriter::in_memory("small.root").unwrap();
+        writer.write_object("TObjString", "a", "", b"x").unwrap();
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+        assert!(!root.is_large_file());
+        assert!(!root.directory().unwrap().is_large());
+        assert!(!root["a"].is_large());
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn read_unknown_compression_leniently() {
+        let mut buf = small_file(&[("a", &[7; 300], true)]);
+        let at = buf.windows(3).position(|w| w == b"ZL\x08").unwrap();
+        buf[at..at + 2].copy_from_slice(b"QQ");
+        let root = RootFile::from_bytes(buf).unwrap();
+        let key = &root.keys()[0];
+
+        assert!(root.read_object(key).is_err());
+        let object = root.read_object_lenient(key).unwrap();
+        assert_eq!(&object.data[..2], b"QQ");
+        assert_eq!(object.data.len(), key.stored_len() as usize);
+        assert!(object.warning.unwrap().contains("QQ"));
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn borrow_uncompressed_objects() {
+        let buf = small_file(&[("a", b"plain", false), ("b", &[7; 300], true)]);
+        let root = RootFile::from_slice(&buf).unwrap();
+        let (plain, compressed) = (&root.keys()[0], &root.keys()[1]);
+        assert!(matches!(
+            root.object_bytes(plain).unwrap(),
+            Cow::Borrowed(b"plain")
+        ));
+        assert_eq!(
+            root.object_bytes(compressed).unwrap(),
+            Cow::<[u8]>::Owned(vec![7; 300])
+        );
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn read_from_stream() {
+        struct Forward<R>(R);
+        impl<R: std::io::Read> std::io::Read for Forward<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+
+        let buf = small_file(&[("a", b"plain", true)]);
+        let root = RootFile::from_stream(Forward(buf.as_slice())).unwrap();
+        assert_eq!(root.read_object(&root.keys()[0]).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn open_reports_path() {
+        let err = RootFile::open("no/such/file.root").unwrap_err();
+        assert!(err.to_string().contains("no/such/file.root"));
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn cancelled_scan() {
+        let buf = small_file(&[("a", b"plain", false)]);
+        let cancel = AtomicBool::new(true);
+        let root = RootFile::with_cancel(Cursor::new(buf), &cancel);
+        assert!(matches!(root, Err(RootIoError::Cancelled)));
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn reject_corrupt_key_lengths() {
+        let root = RootFile::from_bytes(small_file(&[("a", b"plain", false)])).unwrap();
+        let mut key = root.keys()[0].clone();
+        key.nbytes = 1;
+        assert!(matches!(
+            root.read_raw_object(&key),
+            Err(RootIoError::InvalidFormatError)
+        ));
+        key.nbytes = u32::MAX;
+        key.obj_len = u32::MAX;
+        assert!(key.is_compressed());
+        assert!(matches!(
+            root.read_raw_object(&key),
+            Err(RootIoError::InvalidFormatError)
+        ));
+    }
+
+    #[test]
+    fn shareable_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<RootFile<std::fs::File>>();
+    }
+
     #[test]
     fn open_file() {
-        let file = std::fs::File::open("delphes.root").unwrap();
-        let root = RootFile::new(file);
-        assert!(root.is_ok());
-        let root = root.unwrap();
-        dbg!(&root);
-        assert!(root.is_large_file());
+        let mut writer = RootFileWriter::in_memory("memory.root").unwrap();
+        writer
+            .write_object("TObjString", "a", "", b"payload")
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("root_reader_{}.root", std::process::id()));
+        std::fs::write(&path, writer.into_bytes().unwrap()).unwrap();
+
+        let root = RootFile::open(&path).unwrap();
+        assert!(!root.is_large_file());
+        assert_eq!(root.keys().len(), 1);
+        assert_eq!(root.read_object(&root.keys()[0]).unwrap(), b"payload");
+        std::fs::remove_file(path).unwrap();
     }
 }
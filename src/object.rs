@@ -0,0 +1,788 @@
+use crate::internal::*;
+use crate::{DecodeContext, ElementType, RootFile, RootIoError, RootKey, StreamerInfo};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, Write};
+
+/// Marks the byte count preceding a versioned object (`kByteCountMask`).
+pub(crate) const BYTE_COUNT_MASK: u32 = 0x4000_0000;
+/// `fBits` of a streamed `TObject`: `kIsOnHeap | kNotDeleted`.
+const TOBJECT_BITS: u32 = 0x0300_0000;
+/// `kIsReferenced`: the `TObject` is followed by a process ID.
+const IS_REFERENCED: u32 = 0x10;
+
+/// Marks a class name written for the first time (`kNewClassTag`).
+const NEW_CLASS_TAG: u32 = 0xffff_ffff;
+/// Marks a reference to an already written class (`kClassMask`).
+const CLASS_MASK: u32 = 0x8000_0000;
+/// Added to buffer offsets stored in tags so that they are never null.
+const MAP_OFFSET: u32 = 2;
+
+/// An object which can be streamed into a key by `RootFileWriter::write`.
+pub trait ToRoot {
+    /// Class name stored in the key, e.g. `TObjString`.
+    fn class_name(&self) -> String;
+
+    /// Title stored in the key.
+    fn title(&self) -> &str {
+        ""
+    }
+
+    /// Streamer info describing the classes of the object, stored in the
+    /// file so that ROOT can read classes it has no dictionary for. Classes
+    /// built into ROOT can leave this empty.
+    fn streamer_infos(&self) -> Vec<StreamerInfo> {
+        vec![]
+    }
+
+    /// Streams the object into `buf`.
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError>;
+}
+
+/// An object which can be read from a key by `RootFile::get`.
+pub trait FromRoot: Sized {
+    /// The ROOT class read, e.g. `TParameter<double>`.
+    fn class_name() -> String;
+
+    /// Whether objects stored with the class `class_name` can be read as
+    /// `Self`.
+    fn accepts(class_name: &str) -> bool {
+        class_name == Self::class_name()
+    }
+
+    /// Reads the object from `buf`, starting at its byte count.
+    fn from_root(buf: &mut ReadBuffer, ctx: &DecodeContext) -> Result<Self, RootIoError>;
+
+    /// The layout `from_root` expects, checked against the streamer info
+    /// of the file by `RootFile::get` if given.
+    fn streamer_info() -> Option<StreamerInfo> {
+        None
+    }
+}
+
+/// Types of the members of a `#[derive(RootClass)]` struct.
+pub trait Member: Sized {
+    const ELEMENT_TYPE: ElementType;
+    /// Length of a fixed-size array, or 0 for a scalar.
+    const ARRAY_LENGTH: i32 = 0;
+
+    fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError>;
+}
+
+macro_rules! member {
+    ( $t:ty, $element_type:ident, $read:ident ) => {
+        impl Member for $t {
+            const ELEMENT_TYPE: ElementType = ElementType::$element_type;
+
+            fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+                Ok(buf.$read::<BigEndian>()?)
+            }
+        }
+    };
+}
+
+member!(i16, Short, read_i16);
+member!(i32, Int, read_i32);
+member!(i64, Long64, read_i64);
+member!(u16, UShort, read_u16);
+member!(u32, UInt, read_u32);
+member!(u64, ULong64, read_u64);
+member!(f32, Float, read_f32);
+member!(f64, Double, read_f64);
+
+impl Member for i8 {
+    const ELEMENT_TYPE: ElementType = ElementType::Char;
+
+    fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+        Ok(buf.read_i8()?)
+    }
+}
+
+impl Member for u8 {
+    const ELEMENT_TYPE: ElementType = ElementType::UChar;
+
+    fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+        Ok(buf.read_u8()?)
+    }
+}
+
+impl Member for bool {
+    const ELEMENT_TYPE: ElementType = ElementType::Bool;
+
+    fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+        Ok(buf.read_u8()? != 0)
+    }
+}
+
+impl Member for String {
+    const ELEMENT_TYPE: ElementType = ElementType::TString;
+
+    fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+        buf.read_string()
+    }
+}
+
+impl<T: Member + Copy + Default, const N: usize> Member for [T; N] {
+    const ELEMENT_TYPE: ElementType = T::ELEMENT_TYPE;
+    const ARRAY_LENGTH: i32 = N as i32;
+
+    fn read_member(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+        let mut array = [T::default(); N];
+        for value in array.iter_mut() {
+            *value = T::read_member(buf)?;
+        }
+        Ok(array)
+    }
+}
+
+/// Buffer an object is streamed into, as `TBufferFile` in write mode.
+///
+/// Class tags refer to positions counted from the start of the key, so the
+/// buffer has to know the length of the key header it will be stored after.
+#[derive(Debug)]
+pub struct WriteBuffer {
+    data: Vec<u8>,
+    displacement: u32,
+    classes: HashMap<String, u32>,
+}
+
+impl WriteBuffer {
+    pub(crate) fn new(displacement: u32) -> Self {
+        Self {
+            data: vec![],
+            displacement,
+            classes: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn displacement(&self) -> u32 {
+        self.displacement
+    }
+
+    pub(crate) fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Streams `body` preceded by the byte count and `version` header.
+    pub fn write_versioned(
+        &mut self,
+        version: u16,
+        body: impl FnOnce(&mut Self) -> Result<(), RootIoError>,
+    ) -> Result<(), RootIoError> {
+        let start = self.data.len();
+        self.write_u32::<BigEndian>(0)?;
+        self.write_u16::<BigEndian>(version)?;
+        body(self)?;
+        self.set_byte_count(start);
+        Ok(())
+    }
+
+    /// Streams the `TObject` base of an object, which carries no byte count.
+    pub fn write_tobject(&mut self, bits: u32) -> Result<(), RootIoError> {
+        self.write_u16::<BigEndian>(1)?;
+        self.write_u32::<BigEndian>(0)?;
+        self.write_u32::<BigEndian>(TOBJECT_BITS | bits)?;
+        Ok(())
+    }
+
+    pub fn write_string(&mut self, s: &str) -> Result<(), RootIoError> {
+        write_string(self, s)
+    }
+
+    /// Streams `body` as an object of class `class_name` held by pointer,
+    /// i.e. preceded by its byte count and class tag (`WriteObjectAny`).
+    pub fn write_object_any(
+        &mut self,
+        class_name: &str,
+        body: impl FnOnce(&mut Self) -> Result<(), RootIoError>,
+    ) -> Result<(), RootIoError> {
+        let start = self.data.len();
+        self.write_u32::<BigEndian>(0)?;
+        match self.classes.get(class_name) {
+            Some(&tag) => self.write_u32::<BigEndian>(tag | CLASS_MASK)?,
+            None => {
+                let tag = self.displacement + self.data.len() as u32 + MAP_OFFSET;
+                self.classes.insert(class_name.to_string(), tag);
+                self.write_u32::<BigEndian>(NEW_CLASS_TAG)?;
+                self.write_all(class_name.as_bytes())?;
+                self.write_u8(0)?;
+            }
+        }
+        body(self)?;
+        self.set_byte_count(start);
+        Ok(())
+    }
+
+    // The count covers everything after itself.
+    fn set_byte_count(&mut self, start: usize) {
+        let count = (self.data.len() - start - 4) as u32 | BYTE_COUNT_MASK;
+        self.data[start..start + 4].copy_from_slice(&count.to_be_bytes());
+    }
+}
+
+impl Write for WriteBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Buffer an object is read from, as `TBufferFile` in read mode.
+///
+/// Like `WriteBuffer`, it has to know the length of the key header the
+/// object was stored after to resolve class tags.
+#[derive(Debug)]
+pub struct ReadBuffer<'a> {
+    data: &'a [u8],
+    pos: usize,
+    displacement: u32,
+    classes: HashMap<u32, String>,
+}
+
+impl<'a> ReadBuffer<'a> {
+    pub(crate) fn new(data: &'a [u8], displacement: u32) -> Self {
+        Self {
+            data,
+            pos: 0,
+            displacement,
+            classes: HashMap::new(),
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// The bytes from `start` to `end`, wherever the buffer is.
+    pub(crate) fn slice(&self, start: usize, end: usize) -> Result<&'a [u8], RootIoError> {
+        self.data
+            .get(start..end)
+            .ok_or(RootIoError::InvalidFormatError)
+    }
+
+    /// Moves to `pos`, the end of an object as returned by `read_version`
+    /// or `read_object_any`, skipping members which were not read.
+    pub fn skip_to(&mut self, pos: usize) -> Result<(), RootIoError> {
+        if pos < self.pos || pos > self.data.len() {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Reads the byte count and version of an object, returning the version
+    /// and the position the object ends at.
+    pub fn read_version(&mut self) -> Result<(u16, usize), RootIoError> {
+        let count = self.read_u32::<BigEndian>()?;
+        if count & BYTE_COUNT_MASK == 0 {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        let end = self.pos + (count & !BYTE_COUNT_MASK) as usize;
+        Ok((self.read_u16::<BigEndian>()?, end))
+    }
+
+    /// Reads the `TObject` base of an object, returning its `fBits`.
+    pub fn read_tobject(&mut self) -> Result<u32, RootIoError> {
+        self.read_u16::<BigEndian>()?;
+        self.read_u32::<BigEndian>()?;
+        let bits = self.read_u32::<BigEndian>()?;
+        if bits & IS_REFERENCED != 0 {
+            self.read_u16::<BigEndian>()?;
+        }
+        Ok(bits)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, RootIoError> {
+        read_string(self)
+    }
+
+    /// Reads the byte count and class tag of an object held by pointer
+    /// (`ReadObjectAny`), returning its class name and the position it ends
+    /// at, or `None` for a null pointer. The object itself is left unread.
+    pub fn read_object_any(&mut self) -> Result<Option<(String, usize)>, RootIoError> {
+        let count = self.read_u32::<BigEndian>()?;
+        if count == 0 {
+            return Ok(None);
+        }
+        if count & BYTE_COUNT_MASK == 0 {
+            return Err(RootIoError::Unimplemented(
+                "Reading object references".to_string(),
+            ));
+        }
+        let end = self.pos + (count & !BYTE_COUNT_MASK) as usize;
+        let tag_at = self.displacement + self.pos as u32 + MAP_OFFSET;
+        let class_name = match self.read_u32::<BigEndian>()? {
+            NEW_CLASS_TAG => {
+                let mut name = vec![];
+                loop {
+                    match self.read_u8()? {
+                        0 => break,
+                        c => name.push(c),
+                    }
+                }
+                let name = String::from_utf8_lossy(&name).to_string();
+                self.classes.insert(tag_at, name.clone());
+                name
+            }
+            tag if tag & CLASS_MASK != 0 => self
+                .classes
+                .get(&(tag & !CLASS_MASK))
+                .cloned()
+                .ok_or(RootIoError::InvalidFormatError)?,
+            _ => {
+                return Err(RootIoError::Unimplemented(
+                    "Reading object references".to_string(),
+                ))
+            }
+        };
+        if end > self.data.len() {
+            return Err(RootIoError::InvalidFormatError);
+        }
+        Ok(Some((class_name, end)))
+    }
+}
+
+impl Read for ReadBuffer<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = (&self.data[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Reads a `TList`, keeping the items `read_item` returns for their class
+/// name. Items are read from the start of their byte count and version.
+pub(crate) fn read_list<T>(
+    buf: &mut ReadBuffer,
+    mut read_item: impl FnMut(&str, &mut ReadBuffer) -> Result<Option<T>, RootIoError>,
+) -> Result<Vec<T>, RootIoError> {
+    let (version, end) = buf.read_version()?;
+    if version < 4 {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    buf.read_tobject()?;
+    buf.read_string()?;
+    let count = buf.read_u32::<BigEndian>()?;
+    let mut items = vec![];
+    for _ in 0..count {
+        if let Some((class_name, item_end)) = buf.read_object_any()? {
+            items.extend(read_item(&class_name, buf)?);
+            buf.skip_to(item_end)?;
+        }
+        // Draw option.
+        buf.read_string()?;
+    }
+    buf.skip_to(end)?;
+    Ok(items)
+}
+
+/// Streams a `TList` holding `items`, each written by `stream_item`.
+pub(crate) fn write_list<T>(
+    buf: &mut WriteBuffer,
+    items: &[T],
+    class_name: &str,
+    stream_item: impl Fn(&T, &mut WriteBuffer) -> Result<(), RootIoError>,
+) -> Result<(), RootIoError> {
+    buf.write_versioned(5, |buf| {
+        buf.write_tobject(0)?;
+        buf.write_string("")?;
+        buf.write_u32::<BigEndian>(items.len() as u32)?;
+        for item in items {
+            buf.write_object_any(class_name, |buf| stream_item(item, buf))?;
+            // Empty draw option.
+            buf.write_u8(0)?;
+        }
+        Ok(())
+    })
+}
+
+/// Streams the `TList` `list`, as read from a key of the same header
+/// length, with `items` appended to it.
+pub(crate) fn append_list<T>(
+    buf: &mut WriteBuffer,
+    list: &[u8],
+    items: &[T],
+    class_name: &str,
+    stream_item: impl Fn(&T, &mut WriteBuffer) -> Result<(), RootIoError>,
+) -> Result<(), RootIoError> {
+    let mut reader = Cursor::new(list);
+    let byte_count = reader.read_u32::<BigEndian>()?;
+    let version = reader.read_u16::<BigEndian>()?;
+    let len = (byte_count & !BYTE_COUNT_MASK) as usize + 4;
+    if byte_count & BYTE_COUNT_MASK == 0 || version < 4 || len > list.len() {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    reader.read_u16::<BigEndian>()?;
+    reader.read_u32::<BigEndian>()?;
+    if reader.read_u32::<BigEndian>()? & IS_REFERENCED != 0 {
+        reader.read_u16::<BigEndian>()?;
+    }
+    read_string(&mut reader)?;
+    let count_at = reader.position() as usize;
+    let count = reader.read_u32::<BigEndian>()? + items.len() as u32;
+
+    let start = buf.data.len();
+    buf.write_all(&list[..len])?;
+    buf.data[start + count_at..start + count_at + 4].copy_from_slice(&count.to_be_bytes());
+    for item in items {
+        buf.write_object_any(class_name, |buf| stream_item(item, buf))?;
+        buf.write_u8(0)?;
+    }
+    buf.set_byte_count(start);
+    Ok(())
+}
+
+/// An object of a class this crate has no reader for, such as a `TCanvas`
+/// or a `TGeoManager`, kept as the bytes it was streamed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpaqueObject {
+    pub class_name: String,
+    /// The class version the object was streamed with.
+    pub version: u16,
+    /// The streamed object, starting with its byte count and version.
+    pub data: Vec<u8>,
+    /// The layout of this version of the class, if the file stores it.
+    pub streamer_info: Option<StreamerInfo>,
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// Reads the object `path`, given as for `key`, as an `O`.
+    pub fn get<O: FromRoot>(&self, path: &str) -> Result<O, RootIoError> {
+        let key = self.key(path)?;
+        if !O::accepts(&key.class_name) {
+            return Err(
+                unsupported_class(key).unwrap_or_else(|| RootIoError::TypeMismatch {
+                    path: path.to_string(),
+                    found: key.class_name.clone(),
+                    expected: O::class_name(),
+                }),
+            );
+        }
+        let data = self.read_object(key)?;
+        let ctx = DecodeContext {
+            key,
+            version: object_version(&data)?,
+        };
+        if let Some(expected) = O::streamer_info() {
+            let infos = self.streamer_infos()?;
+            let stored = infos.iter().find(|info| {
+                info.class_name == key.class_name && info.class_version == ctx.version as i32
+            });
+            if stored.is_some_and(|info| info.checksum() != expected.checksum()) {
+                return Err(RootIoError::StreamerMismatch {
+                    class_name: key.class_name.clone(),
+                    version: ctx.version,
+                });
+            }
+        }
+        O::from_root(&mut ReadBuffer::new(&data, key.key_len as u32), &ctx)
+    }
+
+    /// Reads the object of `key` without decoding it, along with its member
+    /// layout from the streamer info of the file.
+    pub fn read_opaque(&self, key: &RootKey) -> Result<OpaqueObject, RootIoError> {
+        self.opaque_object(key, self.read_object(key)?)
+    }
+
+    pub(crate) fn opaque_object(
+        &self,
+        key: &RootKey,
+        data: Vec<u8>,
+    ) -> Result<OpaqueObject, RootIoError> {
+        let version = object_version(&data)?;
+        let streamer_info = self
+            .streamer_infos()?
+            .into_iter()
+            .find(|info| info.class_name == key.class_name && info.class_version == version as i32);
+        Ok(OpaqueObject {
+            class_name: key.class_name.clone(),
+            version,
+            data,
+            streamer_info,
+        })
+    }
+}
+
+/// A specific error for a key of a class known to need a reader this crate
+/// does not have, rather than a type mismatch.
+pub(crate) fn unsupported_class(key: &RootKey) -> Option<RootIoError> {
+    let class_name = key.class_name.as_str();
+    let reason = if class_name.ends_with("RNTuple") {
+        "RNTuple data cannot be read by this crate"
+    } else if ["TH1", "TH2", "TH3", "TProfile"]
+        .iter()
+        .any(|prefix| class_name.starts_with(prefix))
+    {
+        "histograms are not decoded, but read_opaque returns their streamed bytes"
+    } else if class_name.starts_with("TGraph") {
+        "graphs are not decoded, but read_opaque returns their streamed bytes"
+    } else if class_name == "TTree" || class_name.starts_with("TNtuple") {
+        "branches cannot be read, only the totals of a tree by tree_totals"
+    } else {
+        return None;
+    };
+    Some(RootIoError::UnsupportedClass {
+        name: key.name.clone(),
+        class_name: class_name.to_string(),
+        reason,
+    })
+}
+
+/// The class version of the object streamed to `data`.
+pub(crate) fn object_version(data: &[u8]) -> Result<u16, RootIoError> {
+    let mut reader = Cursor::new(data);
+    let first = reader.read_u32::<BigEndian>()?;
+    // Objects streamed without a byte count start with the version.
+    Ok(if first & BYTE_COUNT_MASK != 0 {
+        reader.read_u16::<BigEndian>()?
+    } else {
+        (first >> 16) as u16
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TNamed {
+    pub name: String,
+    pub title: String,
+}
+
+impl FromRoot for TNamed {
+    fn class_name() -> String {
+        "TNamed".to_string()
+    }
+
+    fn from_root(buf: &mut ReadBuffer, _: &DecodeContext) -> Result<Self, RootIoError> {
+        let (_, end) = buf.read_version()?;
+        buf.read_tobject()?;
+        let name = buf.read_string()?;
+        let title = buf.read_string()?;
+        buf.skip_to(end)?;
+        Ok(Self { name, title })
+    }
+}
+
+impl ToRoot for TNamed {
+    fn class_name(&self) -> String {
+        "TNamed".to_string()
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_versioned(1, |buf| {
+            buf.write_tobject(0)?;
+            buf.write_string(&self.name)?;
+            buf.write_string(&self.title)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TObjString {
+    pub string: String,
+}
+
+impl FromRoot for TObjString {
+    fn class_name() -> String {
+        "TObjString".to_string()
+    }
+
+    fn from_root(buf: &mut ReadBuffer, _: &DecodeContext) -> Result<Self, RootIoError> {
+        let (_, end) = buf.read_version()?;
+        buf.read_tobject()?;
+        let string = buf.read_string()?;
+        buf.skip_to(end)?;
+        Ok(Self { string })
+    }
+}
+
+impl ToRoot for TObjString {
+    fn class_name(&self) -> String {
+        "TObjString".to_string()
+    }
+
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_versioned(1, |buf| {
+            buf.write_tobject(0)?;
+            buf.write_string(&self.string)
+        })
+    }
+}
+
+/// Value types which have a `TParameter` instantiation in ROOT.
+pub trait ParameterValue: Copy {
+    /// The template argument as ROOT spells it.
+    const TYPE_NAME: &'static str;
+
+    fn write_value(self, buf: &mut WriteBuffer) -> Result<(), RootIoError>;
+
+    fn read_value(buf: &mut ReadBuffer) -> Result<Self, RootIoError>;
+}
+
+macro_rules! parameter_value {
+    ( $t:ty, $name:expr, $write:ident, $read:ident ) => {
+        impl ParameterValue for $t {
+            const TYPE_NAME: &'static str = $name;
+
+            fn write_value(self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+                buf.$write::<BigEndian>(self)?;
+                Ok(())
+            }
+
+            fn read_value(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+                Ok(buf.$read::<BigEndian>()?)
+            }
+        }
+    };
+}
+
+parameter_value!(f64, "double", write_f64, read_f64);
+parameter_value!(f32, "float", write_f32, read_f32);
+parameter_value!(i32, "int", write_i32, read_i32);
+parameter_value!(i64, "Long64_t", write_i64, read_i64);
+
+impl ParameterValue for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn write_value(self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_u8(self as u8)?;
+        Ok(())
+    }
+
+    fn read_value(buf: &mut ReadBuffer) -> Result<Self, RootIoError> {
+        Ok(buf.read_u8()? != 0)
+    }
+}
+
+/// A named value such as a cross section or a scale factor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TParameter<T: ParameterValue> {
+    pub name: String,
+    pub value: T,
+}
+
+impl<T: ParameterValue> FromRoot for TParameter<T> {
+    fn class_name() -> String {
+        format!("TParameter<{}>", T::TYPE_NAME)
+    }
+
+    fn from_root(buf: &mut ReadBuffer, _: &DecodeContext) -> Result<Self, RootIoError> {
+        let (_, end) = buf.read_version()?;
+        buf.read_tobject()?;
+        let name = buf.read_string()?;
+        let value = T::read_value(buf)?;
+        buf.skip_to(end)?;
+        Ok(Self { name, value })
+    }
+}
+
+impl<T: ParameterValue> ToRoot for TParameter<T> {
+    fn class_name(&self) -> String {
+        format!("TParameter<{}>", T::TYPE_NAME)
+    }
+
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_versioned(2, |buf| {
+            buf.write_tobject(0)?;
+            buf.write_string(&self.name)?;
+            self.value.write_value(buf)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TNamed, TObjString, TParameter, ToRoot, WriteBuffer};
+    use crate::{RootFile, RootFileWriter, RootIoError};
+    use std::io::Cursor;
+
+    #[test]
+    fn stream_objects() {
+        let mut buf = WriteBuffer::new(0);
+        TObjString {
+            string: "abc".to_string(),
+        }
+        .stream(&mut buf)
+        .unwrap();
+        assert_eq!(
+            buf.into_inner(),
+            [
+                0x40, 0, 0, 16, 0, 1, // byte count, version
+                0, 1, 0, 0, 0, 0, 3, 0, 0, 0, // TObject
+                3, b'a', b'b', b'c',
+            ]
+        );
+
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "meta.root", "").unwrap();
+        let named = TNamed {
+            name: "provenance".to_string(),
+            title: "git abc123".to_string(),
+        };
+        writer.write(&named.name, &named).unwrap();
+        let scale = TParameter {
+            name: "scale".to_string(),
+            value: 0.5f64,
+        };
+        writer.write("scale", &scale).unwrap();
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+
+        let keys = root.keys();
+        assert_eq!(keys[0].class_name, "TNamed");
+        assert_eq!(keys[0].title, "git abc123");
+        assert_eq!(keys[1].class_name, "TParameter<double>");
+        let data = root.read_object(&keys[1]).unwrap();
+        assert_eq!(&data[data.len() - 8..], &0.5f64.to_be_bytes());
+    }
+
+    #[test]
+    fn get_typed_objects() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "meta.root", "").unwrap();
+        let named = TNamed {
+            name: "provenance".to_string(),
+            title: "git abc123".to_string(),
+        };
+        writer.write(&named.name, &named).unwrap();
+        let scale = TParameter {
+            name: "scale".to_string(),
+            value: 0.5f64,
+        };
+        writer.write("scale", &scale).unwrap();
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+
+        assert_eq!(root.get::<TNamed>("provenance").unwrap(), named);
+        assert_eq!(root.get::<TParameter<f64>>("scale;1").unwrap(), scale);
+        assert!(matches!(
+            root.get::<TParameter<i32>>("scale"),
+            Err(RootIoError::TypeMismatch { found, expected, .. })
+                if found == "TParameter<double>" && expected == "TParameter<int>"
+        ));
+        assert!(matches!(
+            root.get::<TObjString>("missing"),
+            Err(RootIoError::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn explain_unsupported_classes() {
+        let mut writer = RootFileWriter::in_memory("ntuple.root").unwrap();
+        writer
+            .write_object("ROOT::RNTuple", "Events", "", &[0; 64])
+            .unwrap();
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+        let err = root.get::<TNamed>("Events").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Events is a ROOT::RNTuple: RNTuple data cannot be read by this crate"
+        );
+        assert!(matches!(
+            root.tree_totals(&root["Events"]),
+            Err(RootIoError::UnsupportedClass { .. })
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{RootFile, RootIoError, RootKey};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+/// Number of records each stage may run ahead of the next one.
+const QUEUE_SIZE: usize = 16;
+
+type Decoded = (usize, Result<Vec<u8>, RootIoError>);
+
+/// Objects fetched but not yet consumed, limited in number and in
+/// decompressed bytes.
+struct Budget {
+    max_bytes: u64,
+    /// Bounds the objects decompressed ahead of one which is slow to
+    /// arrive, which wait to be put back in order.
+    max_objects: usize,
+    state: Mutex<InFlight>,
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct InFlight {
+    bytes: u64,
+    objects: usize,
+    peak_bytes: u64,
+    peak_objects: usize,
+    /// Whether the consumer has stopped.
+    stopped: bool,
+}
+
+impl Budget {
+    fn new(max_bytes: u64, max_objects: usize) -> Self {
+        Self {
+            max_bytes,
+            max_objects,
+            state: Mutex::default(),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits until an object of `bytes` more fits, returning false if the
+    /// consumer has stopped. An object larger than the limit is let
+    /// through alone.
+    fn acquire(&self, bytes: u64) -> bool {
+        let mut state = self.state();
+        while !state.stopped
+            && state.objects > 0
+            && (state.objects >= self.max_objects || state.bytes + bytes > self.max_bytes)
+        {
+            state = self
+                .freed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.bytes += bytes;
+        state.objects += 1;
+        state.peak_bytes = state.peak_bytes.max(state.bytes);
+        state.peak_objects = state.peak_objects.max(state.objects);
+        !state.stopped
+    }
+
+    fn release(&self, bytes: u64) {
+        let mut state = self.state();
+        state.bytes -= bytes;
+        state.objects -= 1;
+        self.freed.notify_one();
+    }
+
+    fn stop(&self) {
+        self.state().stopped = true;
+        self.freed.notify_one();
+    }
+
+    fn state(&self) -> MutexGuard<'_, InFlight> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: Read + Seek + Send> RootFile<T> {
+    /// Reads and decompresses the payloads of `keys`, handing them to
+    /// `consumer` in the order of `keys`.
+    ///
+    /// Raw bytes are fetched on one thread and decompressed on `workers`
+    /// threads (all available cores if `0`), connected by bounded queues, so
+    /// that IO latency overlaps with decompression. Objects are fetched at
+    /// most a few queue lengths ahead of the one `consumer` waits for, so
+    /// that a slow object does not let the others pile up. The first error,
+    /// either from reading or from `consumer`, stops the pipeline and is
+    /// returned.
+    pub fn for_each_object<'k, F>(
+        &self,
+        keys: &'k [RootKey],
+        workers: usize,
+        consumer: F,
+    ) -> Result<(), RootIoError>
+    where
+        F: FnMut(&'k RootKey, Vec<u8>) -> Result<(), RootIoError>,
+    {
+        self.for_each_object_bounded(keys, workers, u64::MAX, consumer)
+    }
+
+    /// Same as `for_each_object`, but objects are only fetched ahead while
+    /// the decompressed size of those not yet handed to `consumer` stays
+    /// within `max_bytes_in_flight`, so that a slow consumer bounds memory
+    /// by bytes rather than by a number of objects of any size.
+    pub fn for_each_object_bounded<'k, F>(
+        &self,
+        keys: &'k [RootKey],
+        workers: usize,
+        max_bytes_in_flight: u64,
+        consumer: F,
+    ) -> Result<(), RootIoError>
+    where
+        F: FnMut(&'k RootKey, Vec<u8>) -> Result<(), RootIoError>,
+    {
+        let cancel = AtomicBool::new(false);
+        self.for_each_object_with_cancel(keys, workers, max_bytes_in_flight, &cancel, consumer)
+    }
+
+    /// Same as `for_each_object_bounded`, but stops with
+    /// `RootIoError::Cancelled` as soon as `cancel` is set from another
+    /// thread. The objects handed to `consumer` until then are the partial
+    /// result.
+    pub fn for_each_object_with_cancel<'k, F>(
+        &self,
+        keys: &'k [RootKey],
+        workers: usize,
+        max_bytes_in_flight: u64,
+        cancel: &AtomicBool,
+        consumer: F,
+    ) -> Result<(), RootIoError>
+    where
+        F: FnMut(&'k RootKey, Vec<u8>) -> Result<(), RootIoError>,
+    {
+        let workers = if workers == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            workers
+        };
+        let budget = Budget::new(max_bytes_in_flight, 2 * QUEUE_SIZE + workers);
+        self.run_pipeline(keys, workers, &budget, cancel, consumer)
+    }
+
+    fn run_pipeline<'k, F>(
+        &self,
+        keys: &'k [RootKey],
+        workers: usize,
+        budget: &Budget,
+        cancel: &AtomicBool,
+        mut consumer: F,
+    ) -> Result<(), RootIoError>
+    where
+        F: FnMut(&'k RootKey, Vec<u8>) -> Result<(), RootIoError>,
+    {
+        thread::scope(|scope| {
+            let (raw_tx, raw_rx) = sync_channel::<Decoded>(QUEUE_SIZE);
+            let (decoded_tx, decoded_rx) = sync_channel::<Decoded>(QUEUE_SIZE);
+            // Shared by the workers only, so the IO thread stops once they are gone.
+            let raw_rx = Arc::new(Mutex::new(raw_rx));
+
+            scope.spawn(move || {
+                for (i, key) in keys.iter().enumerate() {
+                    if cancel.load(Ordering::Relaxed) || !budget.acquire(key.obj_len as u64) {
+                        break;
+                    }
+                    let raw = self.read_raw_object(key);
+                    let failed = raw.is_err();
+                    if raw_tx.send((i, raw)).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+
+            for _ in 0..workers {
+                let raw_rx = Arc::clone(&raw_rx);
+                let decoded_tx = decoded_tx.clone();
+                scope.spawn(move || {
+                    while let Some((i, raw)) = next_raw(&raw_rx) {
+                        let decoded = raw.and_then(|raw| {
+                            let key = &keys[i];
+                            let mut buf = Vec::with_capacity(key.obj_len as usize);
+                            key.decompress(&raw)?.read_to_end(&mut buf)?;
+                            Ok(buf)
+                        });
+                        if decoded_tx.send((i, decoded)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(raw_rx);
+            drop(decoded_tx);
+
+            // Workers finish in any order; restore the order of `keys`.
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            let result = (|| {
+                for (i, decoded) in decoded_rx {
+                    pending.insert(i, decoded);
+                    while let Some(decoded) = pending.remove(&next) {
+                        if cancel.load(Ordering::Relaxed) {
+                            return Err(RootIoError::Cancelled);
+                        }
+                        consumer(&keys[next], decoded?)?;
+                        budget.release(keys[next].obj_len as u64);
+                        next += 1;
+                    }
+                }
+                // The IO thread stopped early.
+                if next < keys.len() && cancel.load(Ordering::Relaxed) {
+                    return Err(RootIoError::Cancelled);
+                }
+                Ok(())
+            })();
+            // Unblocks the IO thread if it is waiting for the budget.
+            budget.stop();
+            result
+        })
+    }
+}
+
+fn next_raw(raw_rx: &Mutex<Receiver<Decoded>>) -> Option<Decoded> {
+    raw_rx
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .recv()
+        .ok()
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use super::Budget;
+    use crate::tests::small_file;
+    use crate::{RootFile, RootIoError};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn pipelined_in_order() {
+        let payloads: Vec<Vec<u8>> = (0..50u8).map(|i| vec![i; 100 + i as usize]).collect();
+        let names: Vec<String> = (0..50).map(|i| format!("obj{}", i)).collect();
+        let objects: Vec<_> = names
+            .iter()
+            .zip(&payloads)
+            .enumerate()
+            .map(|(i, (name, payload))| (name.as_str(), payload.as_slice(), i % 2 == 0))
+            .collect();
+        let root = RootFile::from_bytes(small_file(&objects)).unwrap();
+
+        let mut seen = vec![];
+        root.for_each_object(root.keys(), 4, |key, data| {
+            seen.push((key.name.clone(), data));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen.len(), 50);
+        for ((name, data), (expected_name, expected)) in
+            seen.iter().zip(names.iter().zip(&payloads))
+        {
+            assert_eq!(name, expected_name);
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    fn bounded_reordering() {
+        let objects: Vec<_> = (0..100).map(|_| ("obj", &[1u8; 10][..], false)).collect();
+        let root = RootFile::from_bytes(small_file(&objects)).unwrap();
+
+        let no_cancel = AtomicBool::new(false);
+        let budget = Budget::new(u64::MAX, 8);
+        let mut count = 0;
+        root.run_pipeline(root.keys(), 4, &budget, &no_cancel, |_, _| {
+            if count == 0 {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            count += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 100);
+        assert!(budget.state().peak_objects <= 8);
+    }
+
+    #[test]
+    fn bounded_bytes_in_flight() {
+        let objects: Vec<_> = (0..20).map(|_| ("obj", &[1u8; 100][..], true)).collect();
+        let root = RootFile::from_bytes(small_file(&objects)).unwrap();
+
+        let no_cancel = AtomicBool::new(false);
+        let budget = Budget::new(250, usize::MAX);
+        let mut count = 0;
+        root.run_pipeline(root.keys(), 4, &budget, &no_cancel, |_, data| {
+            assert_eq!(data.len(), 100);
+            count += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 20);
+        assert!(budget.state().peak_bytes <= 250);
+
+        // An object larger than the limit still goes through, alone.
+        let budget = Budget::new(50, usize::MAX);
+        root.run_pipeline(root.keys(), 4, &budget, &no_cancel, |_, _| Ok(()))
+            .unwrap();
+        assert_eq!(budget.state().peak_bytes, 100);
+
+        // A failing consumer must not leave the IO thread waiting.
+        let result =
+            root.for_each_object_bounded(root.keys(), 2, 50, |_, _| Err(RootIoError::Cancelled));
+        assert!(matches!(result, Err(RootIoError::Cancelled)));
+    }
+
+    #[test]
+    fn cancel_pipeline() {
+        let objects: Vec<_> = (0..50).map(|_| ("obj", &[1u8; 10][..], false)).collect();
+        let root = RootFile::from_bytes(small_file(&objects)).unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let mut count = 0;
+        let result = root.for_each_object_with_cancel(root.keys(), 2, u64::MAX, &cancel, |_, _| {
+            count += 1;
+            if count == 10 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            Ok(())
+        });
+        assert!(matches!(result, Err(RootIoError::Cancelled)));
+        assert_eq!(count, 10);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::object::{object_version, ReadBuffer};
+use crate::{FromRoot, OpaqueObject, RootFile, RootIoError, RootKey};
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+type Decoder = Box<
+    dyn Fn(&mut ReadBuffer, &DecodeContext) -> Result<Box<dyn Any + Send>, RootIoError>
+        + Send
+        + Sync,
+>;
+
+/// What a registered decoder is told about the object it decodes.
+#[derive(Debug)]
+pub struct DecodeContext<'a> {
+    pub key: &'a RootKey,
+    /// The class version the object was streamed with.
+    pub version: u16,
+}
+
+/// Decoders for classes the crate does not know, such as those of an
+/// experiment's event data model, registered by class name and optionally
+/// by class version.
+#[derive(Default)]
+pub struct ClassRegistry {
+    decoders: HashMap<(String, Option<u16>), Decoder>,
+}
+
+impl std::fmt::Debug for ClassRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+/// An object decoded through a `ClassRegistry`.
+#[derive(Debug)]
+pub enum Decoded {
+    /// The value returned by the registered decoder.
+    Native(Box<dyn Any + Send>),
+    /// The object of a class without a decoder.
+    Opaque(OpaqueObject),
+}
+
+impl Decoded {
+    /// The decoded value if it is a `T`, or `self` otherwise.
+    pub fn downcast<T: Any>(self) -> Result<T, Self> {
+        match self {
+            Decoded::Native(value) => value
+                .downcast()
+                .map(|value| *value)
+                .map_err(Decoded::Native),
+            opaque => Err(opaque),
+        }
+    }
+}
+
+impl ClassRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `decoder` for every version of `class_name`. The decoder
+    /// reads the object from the start of its byte count.
+    pub fn register<T: Any + Send>(
+        &mut self,
+        class_name: &str,
+        decoder: impl Fn(&mut ReadBuffer, &DecodeContext) -> Result<T, RootIoError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> &mut Self {
+        self.insert(class_name, None, decoder)
+    }
+
+    /// Registers `decoder` for one version of `class_name`, taking
+    /// precedence over a decoder registered for every version.
+    pub fn register_version<T: Any + Send>(
+        &mut self,
+        class_name: &str,
+        version: u16,
+        decoder: impl Fn(&mut ReadBuffer, &DecodeContext) -> Result<T, RootIoError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> &mut Self {
+        self.insert(class_name, Some(version), decoder)
+    }
+
+    /// Registers the `FromRoot` implementation of `T` for `class_name`.
+    pub fn register_type<T: FromRoot + Any + Send>(&mut self, class_name: &str) -> &mut Self {
+        self.insert(class_name, None, T::from_root)
+    }
+
+    fn insert<T: Any + Send>(
+        &mut self,
+        class_name: &str,
+        version: Option<u16>,
+        decoder: impl Fn(&mut ReadBuffer, &DecodeContext) -> Result<T, RootIoError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> &mut Self {
+        self.decoders.insert(
+            (class_name.to_string(), version),
+            Box::new(move |buf, ctx| Ok(Box::new(decoder(buf, ctx)?))),
+        );
+        self
+    }
+
+    fn get(&self, class_name: &str, version: u16) -> Option<&Decoder> {
+        let class_name = class_name.to_string();
+        self.decoders
+            .get(&(class_name.clone(), Some(version)))
+            .or_else(|| self.decoders.get(&(class_name, None)))
+    }
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// Decodes the object of `key` with the decoder registered for its
+    /// class, falling back to an opaque object.
+    pub fn decode(&self, key: &RootKey, registry: &ClassRegistry) -> Result<Decoded, RootIoError> {
+        let data = self.read_object(key)?;
+        let version = object_version(&data)?;
+        match registry.get(&key.class_name, version) {
+            Some(decoder) => {
+                let mut buf = ReadBuffer::new(&data, key.key_len as u32);
+                decoder(&mut buf, &DecodeContext { key, version }).map(Decoded::Native)
+            }
+            None => self.opaque_object(key, data).map(Decoded::Opaque),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClassRegistry, Decoded};
+    use crate::{RootFile, RootFileWriter, TNamed};
+    use byteorder::{BigEndian, ReadBytesExt};
+    use std::io::Cursor;
+
+    #[derive(Debug, PartialEq)]
+    struct Provenance {
+        name: String,
+        version: u16,
+    }
+
+    #[test]
+    fn decode_registered_classes() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "meta.root", "").unwrap();
+        let named = TNamed {
+            name: "provenance".to_string(),
+            title: "git abc123".to_string(),
+        };
+        writer.write("provenance", &named).unwrap();
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+        let key = root.key("provenance").unwrap();
+
+        let registry = ClassRegistry::new();
+        match root.decode(key, &registry).unwrap() {
+            Decoded::Opaque(object) => assert_eq!(object.class_name, "TNamed"),
+            decoded => panic!("{:?}", decoded),
+        }
+
+        let mut registry = ClassRegistry::new();
+        registry
+            .register("TNamed", |_, _| Ok(0u8))
+            .register_version("TNamed", 1, |buf, ctx| {
+                let (_, end) = buf.read_version()?;
+                buf.read_tobject()?;
+                let name = buf.read_string()?;
+                buf.skip_to(end)?;
+                Ok(Provenance {
+                    name,
+                    version: ctx.version,
+                })
+            })
+            .register("TObjString", |buf, _| Ok(buf.read_u32::<BigEndian>()?));
+        let decoded = root.decode(key, &registry).unwrap();
+        assert_eq!(
+            decoded.downcast::<Provenance>().unwrap(),
+            Provenance {
+                name: "provenance".to_string(),
+                version: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::entry::{block_data_len, block_obj_len, decompress_block, HEADER_SIZE};
+use crate::{RootFile, RootIoError, RootKey};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// Bytes of an uncompressed payload fetched at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Reads the payload of one object, fetching and decompressing one
+/// compression block at a time. Returned by `RootFile::object_reader`.
+///
+/// Seeking locates the block holding the target from the block headers,
+/// which are indexed as they are passed, and decompresses only that block.
+#[derive(Debug)]
+pub struct ObjectReader<'a, T: Read + Seek> {
+    file: &'a RootFile<T>,
+    key: &'a RootKey,
+    /// Offset of the next block in the stored payload.
+    next: u64,
+    /// Offset of the next block in the decompressed payload.
+    next_start: u64,
+    /// Stored and decompressed offsets of the blocks seen so far.
+    index: Vec<(u64, u64)>,
+    /// Offset of the window in the decompressed payload.
+    window_start: u64,
+    window: Cursor<Vec<u8>>,
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// A reader over the (decompressed) object payload of `key`.
+    ///
+    /// Unlike `read_object`, which holds the whole stored and decompressed
+    /// payload in memory, this holds only one block of each at a time (up
+    /// to 16 MiB as ROOT writes them), so memory stays flat for objects of
+    /// hundreds of MB.
+    pub fn object_reader<'a>(&'a self, key: &'a RootKey) -> ObjectReader<'a, T> {
+        ObjectReader {
+            file: self,
+            key,
+            next: 0,
+            next_start: 0,
+            index: vec![],
+            window_start: 0,
+            window: Cursor::new(vec![]),
+        }
+    }
+}
+
+impl<T: Read + Seek> ObjectReader<'_, T> {
+    /// Replaces the window by the next block, returning false at the end.
+    fn fill(&mut self) -> Result<bool, RootIoError> {
+        let stored = self.key.stored_len() as u64;
+        if self.next >= stored {
+            return Ok(false);
+        }
+        let mut reader = self.file.reader();
+        reader.seek(SeekFrom::Start(self.key.obj_begin + self.next))?;
+        let data = if self.key.is_compressed() {
+            self.record(self.next, self.next_start);
+            let mut raw = vec![0; HEADER_SIZE];
+            reader.read_exact(&mut raw)?;
+            let len = HEADER_SIZE + block_data_len(&raw);
+            if self.next + len as u64 > stored {
+                return Err(RootIoError::InvalidFormatError);
+            }
+            raw.resize(len, 0);
+            reader.read_exact(&mut raw[HEADER_SIZE..])?;
+            drop(reader);
+            self.next += len as u64;
+            decompress_block(&raw)?
+        } else {
+            let mut data = vec![0; CHUNK_SIZE.min((stored - self.next) as usize)];
+            reader.read_exact(&mut data)?;
+            self.next += data.len() as u64;
+            data
+        };
+        self.window_start = self.next_start;
+        self.next_start += data.len() as u64;
+        self.window = Cursor::new(data);
+        Ok(true)
+    }
+
+    /// Adds a block to the index unless it is already there.
+    fn record(&mut self, stored: u64, start: u64) {
+        if self.index.last().is_none_or(|&(last, _)| last < stored) {
+            self.index.push((stored, start));
+        }
+    }
+
+    /// Stored and decompressed offsets of the block holding `target`, which
+    /// is below the object length, reading block headers past the indexed
+    /// ones as needed.
+    fn locate(&mut self, target: u64) -> Result<(u64, u64), RootIoError> {
+        if !self.key.is_compressed() {
+            return Ok((target, target));
+        }
+        let (mut stored, mut start) = match self.index.iter().rev().find(|b| b.1 <= target) {
+            Some(&block) => block,
+            None => (0, 0),
+        };
+        let mut reader = self.file.reader();
+        let mut header = [0; HEADER_SIZE];
+        loop {
+            reader.seek(SeekFrom::Start(self.key.obj_begin + stored))?;
+            reader.read_exact(&mut header)?;
+            let len = block_obj_len(&header) as u64;
+            if target < start + len {
+                return Ok((stored, start));
+            }
+            stored += (HEADER_SIZE + block_data_len(&header)) as u64;
+            start += len;
+            if stored >= self.key.stored_len() as u64 {
+                return Err(RootIoError::InvalidFormatError);
+            }
+            self.record(stored, start);
+        }
+    }
+
+    fn position(&self) -> u64 {
+        self.window_start + self.window.position()
+    }
+}
+
+fn to_io_error(err: RootIoError) -> std::io::Error {
+    match err {
+        RootIoError::IOError(err) => err,
+        err => std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+impl<T: Read + Seek> Read for ObjectReader<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = self.window.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let filled = self.fill().map_err(to_io_error)?;
+            if !filled {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<T: Read + Seek> Seek for ObjectReader<'_, T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position().checked_add_signed(offset),
+            SeekFrom::End(offset) => (self.key.obj_len as u64).checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            )
+        })?;
+
+        let window_len = self.window.get_ref().len() as u64;
+        if (self.window_start..self.window_start + window_len).contains(&target) {
+            self.window.set_position(target - self.window_start);
+        } else if target >= self.key.obj_len as u64 {
+            self.next = self.key.stored_len() as u64;
+            self.next_start = target;
+            self.window_start = target;
+            self.window = Cursor::new(vec![]);
+        } else {
+            let (stored, start) = self.locate(target).map_err(to_io_error)?;
+            self.next = stored;
+            self.next_start = start;
+            self.fill().map_err(to_io_error)?;
+            self.window.set_position(target - self.window_start);
+        }
+        Ok(target)
+    }
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use crate::{Compression, RootFile, RootFileWriter};
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn stream_large_objects() {
+        // Larger than one block.
+        let payload: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = RootFileWriter::in_memory("large.root").unwrap();
+        writer.set_compression(Compression::Zlib(1));
+        writer.set_block_len(1000);
+        writer
+            .write_object("TObjString", "blob", "", &payload)
+            .unwrap();
+        writer
+            .write_object("TObjString", "small", "", b"abc")
+            .unwrap();
+        let mut bytes = writer.into_bytes().unwrap();
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+
+        let key = root.key("blob").unwrap();
+        assert!(key.is_compressed());
+        let mut read = vec![];
+        root.object_reader(key).read_to_end(&mut read).unwrap();
+        assert!(read == payload);
+
+        // A block shorter than its header says would shift later offsets.
+        let len_at = key.obj_begin as usize + 6;
+        bytes[len_at..len_at + 3].copy_from_slice(&1001u32.to_le_bytes()[..3]);
+        let corrupt = RootFile::from_bytes(bytes).unwrap();
+        let key = corrupt.key("blob").unwrap();
+        assert!(corrupt.object_reader(key).read_to_end(&mut vec![]).is_err());
+
+        let mut read = String::new();
+        root.object_reader(root.key("small").unwrap())
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, "abc");
+    }
+
+    #[test]
+    fn seek_within_large_objects() {
+        let payload: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = RootFileWriter::in_memory("large.root").unwrap();
+        writer.set_compression(Compression::Zlib(1));
+        writer.set_block_len(4096);
+        writer
+            .write_object("TObjString", "blob", "", &payload)
+            .unwrap();
+        let root = RootFile::from_bytes(writer.into_bytes().unwrap()).unwrap();
+        let mut reader = root.object_reader(root.key("blob").unwrap());
+
+        let mut buf = [0; 4];
+        for &offset in &[7500u64, 4093, 5, 9996, 4096] {
+            assert_eq!(reader.seek(SeekFrom::Start(offset)).unwrap(), offset);
+            reader.read_exact(&mut buf).unwrap();
+            let at = offset as usize;
+            assert_eq!(buf, payload[at..at + 4]);
+        }
+        assert_eq!(reader.seek(SeekFrom::Current(-8)).unwrap(), 4092);
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 9998);
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, payload[9998..]);
+        assert_eq!(reader.seek(SeekFrom::End(10)).unwrap(), 10_010);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert!(reader.seek(SeekFrom::Current(-20_000)).is_err());
+    }
+}
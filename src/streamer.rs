@@ -0,0 +1,636 @@
+use crate::object::{read_list, ReadBuffer, WriteBuffer};
+use crate::{RootFile, RootIoError, RootKey};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+use std::sync::{Mutex, MutexGuard};
+
+/// `TStreamerInfo` class version written.
+const STREAMER_INFO_VERSION: u16 = 9;
+/// Added to the basic type code of fixed-length arrays (`kOffsetL`).
+const OFFSET_L: i32 = 20;
+
+/// Type of a streamed class member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    /// A base class, with the version of it being streamed.
+    Base {
+        version: i32,
+        checksum: u32,
+    },
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    UChar,
+    UShort,
+    UInt,
+    ULong,
+    Long64,
+    ULong64,
+    Bool,
+    TString,
+    /// Any other member, such as an object, a pointer or an STL container,
+    /// with its `fType` code. These are read from files, and written back
+    /// only as they were read.
+    Other {
+        code: i32,
+    },
+}
+
+impl ElementType {
+    /// `fType` code (`TVirtualStreamerInfo::EReadWrite`) for a member named
+    /// `name`; the bases `TObject` and `TNamed` have codes of their own.
+    fn code(self, name: &str) -> i32 {
+        match self {
+            ElementType::Base { .. } => match name {
+                "TObject" => 66,
+                "TNamed" => 67,
+                _ => 0,
+            },
+            ElementType::Char => 1,
+            ElementType::Short => 2,
+            ElementType::Int => 3,
+            ElementType::Long => 4,
+            ElementType::Float => 5,
+            ElementType::Double => 8,
+            ElementType::UChar => 11,
+            ElementType::UShort => 12,
+            ElementType::UInt => 13,
+            ElementType::ULong => 14,
+            ElementType::Long64 => 16,
+            ElementType::ULong64 => 17,
+            ElementType::Bool => 18,
+            ElementType::TString => 65,
+            ElementType::Other { code } => code,
+        }
+    }
+
+    fn from_code(code: i32) -> Option<Self> {
+        Some(match code {
+            1 => ElementType::Char,
+            2 => ElementType::Short,
+            3 => ElementType::Int,
+            4 => ElementType::Long,
+            5 => ElementType::Float,
+            8 => ElementType::Double,
+            11 => ElementType::UChar,
+            12 => ElementType::UShort,
+            13 => ElementType::UInt,
+            14 => ElementType::ULong,
+            16 => ElementType::Long64,
+            17 => ElementType::ULong64,
+            18 => ElementType::Bool,
+            65 => ElementType::TString,
+            _ => return None,
+        })
+    }
+
+    /// In-memory size on a 64 bit platform, as ROOT records it.
+    fn size(self) -> i32 {
+        match self {
+            ElementType::Base { .. } | ElementType::Other { .. } => 0,
+            ElementType::Char | ElementType::UChar | ElementType::Bool => 1,
+            ElementType::Short | ElementType::UShort => 2,
+            ElementType::Int | ElementType::UInt | ElementType::Float => 4,
+            ElementType::Long
+            | ElementType::ULong
+            | ElementType::Long64
+            | ElementType::ULong64
+            | ElementType::Double => 8,
+            ElementType::TString => 24,
+        }
+    }
+
+    fn type_name(self) -> &'static str {
+        match self {
+            ElementType::Base { .. } => "BASE",
+            ElementType::Char => "char",
+            ElementType::Short => "short",
+            ElementType::Int => "int",
+            ElementType::Long => "long",
+            ElementType::Float => "float",
+            ElementType::Double => "double",
+            ElementType::UChar => "unsigned char",
+            ElementType::UShort => "unsigned short",
+            ElementType::UInt => "unsigned int",
+            ElementType::ULong => "unsigned long",
+            ElementType::Long64 => "Long64_t",
+            ElementType::ULong64 => "ULong64_t",
+            ElementType::Bool => "bool",
+            ElementType::TString => "TString",
+            ElementType::Other { .. } => "",
+        }
+    }
+}
+
+/// One entry of a `TStreamerInfo`: a base class or a data member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamerElement {
+    pub name: String,
+    /// The comment of the member in the class declaration.
+    pub title: String,
+    pub element_type: ElementType,
+    /// The type as spelled in the class declaration, e.g. `vector<int>`.
+    pub type_name: String,
+    /// Length of a fixed-size array member, or 0 for a scalar.
+    pub array_length: i32,
+    /// Class name and streamed bytes of an `Other` element read from a
+    /// file, which are written back as they are.
+    streamed: Option<(String, Vec<u8>)>,
+}
+
+impl StreamerElement {
+    fn is_base(&self) -> bool {
+        matches!(self.element_type, ElementType::Base { .. })
+    }
+
+    fn class_name(&self) -> &str {
+        if let Some((class_name, _)) = &self.streamed {
+            return class_name;
+        }
+        match self.element_type {
+            ElementType::Base { .. } => "TStreamerBase",
+            ElementType::TString => "TStreamerString",
+            ElementType::Other { .. } => "TStreamerElement",
+            _ => "TStreamerBasicType",
+        }
+    }
+
+    fn new(name: &str, element_type: ElementType, array_length: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            title: String::new(),
+            element_type,
+            type_name: element_type.type_name().to_string(),
+            array_length,
+            streamed: None,
+        }
+    }
+
+    /// Reads an element of class `class_name`, e.g. `TStreamerBase`, which
+    /// ends at `end`.
+    fn read(buf: &mut ReadBuffer, class_name: &str, end: usize) -> Result<Self, RootIoError> {
+        let start = buf.position();
+        let (version, _) = buf.read_version()?;
+        let (element_version, element_end) = buf.read_version()?;
+        if element_version < 2 {
+            return Err(RootIoError::Unimplemented(format!(
+                "Reading TStreamerElement version {}",
+                element_version
+            )));
+        }
+        let (_, named_end) = buf.read_version()?;
+        buf.read_tobject()?;
+        let name = buf.read_string()?;
+        let title = buf.read_string()?;
+        buf.skip_to(named_end)?;
+        let code = buf.read_i32::<BigEndian>()?;
+        // Size and number of dimensions.
+        buf.read_i32::<BigEndian>()?;
+        let array_length = buf.read_i32::<BigEndian>()?;
+        buf.read_i32::<BigEndian>()?;
+        for _ in 0..5 {
+            buf.read_i32::<BigEndian>()?;
+        }
+        let type_name = buf.read_string()?;
+        buf.skip_to(element_end)?;
+
+        let basic_code = if array_length > 0 {
+            code - OFFSET_L
+        } else {
+            code
+        };
+        let element_type = match class_name {
+            // The checksum of a base is not stored with it.
+            "TStreamerBase" => ElementType::Base {
+                version: if version > 2 {
+                    buf.read_i32::<BigEndian>()?
+                } else {
+                    0
+                },
+                checksum: 0,
+            },
+            "TStreamerBasicType" | "TStreamerString" => {
+                ElementType::from_code(basic_code).unwrap_or(ElementType::Other { code })
+            }
+            _ => ElementType::Other { code },
+        };
+        let streamed = match element_type {
+            ElementType::Other { .. } => {
+                Some((class_name.to_string(), buf.slice(start, end)?.to_vec()))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            name,
+            title,
+            element_type,
+            type_name,
+            array_length,
+            streamed,
+        })
+    }
+
+    fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        let (version, base) = match (self.element_type, &self.streamed) {
+            (_, Some((_, bytes))) => return Ok(buf.write_all(bytes)?),
+            (ElementType::Base { version, .. }, None) => (3, Some(version)),
+            (ElementType::Other { code }, None) => {
+                return Err(RootIoError::Unimplemented(format!(
+                    "Writing streamer elements of type {}",
+                    code
+                )))
+            }
+            _ => (2, None),
+        };
+        buf.write_versioned(version, |buf| {
+            buf.write_versioned(4, |buf| {
+                buf.write_versioned(1, |buf| {
+                    buf.write_tobject(0)?;
+                    buf.write_string(&self.name)?;
+                    buf.write_string(&self.title)
+                })?;
+                let mut code = self.element_type.code(&self.name);
+                let mut size = self.element_type.size();
+                let dim = if self.array_length > 0 {
+                    code += OFFSET_L;
+                    size *= self.array_length;
+                    1
+                } else {
+                    0
+                };
+                buf.write_i32::<BigEndian>(code)?;
+                buf.write_i32::<BigEndian>(size)?;
+                buf.write_i32::<BigEndian>(self.array_length)?;
+                buf.write_i32::<BigEndian>(dim)?;
+                for i in 0..5 {
+                    let max_index = if i == 0 { self.array_length } else { 0 };
+                    buf.write_i32::<BigEndian>(max_index)?;
+                }
+                buf.write_string(&self.type_name)
+            })?;
+            if let Some(base_version) = base {
+                buf.write_i32::<BigEndian>(base_version)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Layout of a class as stored in the `StreamerInfo` record of a file.
+#[derive(Debug, Clone, Eq)]
+pub struct StreamerInfo {
+    pub class_name: String,
+    pub class_version: i32,
+    pub elements: Vec<StreamerElement>,
+    /// The `fCheckSum` of the record, for infos read from a file.
+    stored_checksum: Option<u32>,
+}
+
+impl PartialEq for StreamerInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.class_name == other.class_name
+            && self.class_version == other.class_version
+            && self.elements == other.elements
+            && self.checksum() == other.checksum()
+    }
+}
+
+impl StreamerInfo {
+    pub fn new(class_name: &str, class_version: i32) -> Self {
+        Self {
+            class_name: class_name.to_string(),
+            class_version,
+            elements: vec![],
+            stored_checksum: None,
+        }
+    }
+
+    /// Sets the checksum rather than computing it from the elements, e.g. to
+    /// the one ROOT gives a class whose members are declared with typedefs.
+    pub fn with_checksum(mut self, checksum: u32) -> Self {
+        self.stored_checksum = Some(checksum);
+        self
+    }
+
+    /// Adds a base class; `checksum` is the checksum of the base's own
+    /// streamer info and enters the checksum of this class.
+    pub fn with_base(mut self, class_name: &str, version: i32, checksum: u32) -> Self {
+        self.elements.push(StreamerElement::new(
+            class_name,
+            ElementType::Base { version, checksum },
+            0,
+        ));
+        self
+    }
+
+    pub fn with_member(mut self, name: &str, element_type: ElementType) -> Self {
+        self.elements
+            .push(StreamerElement::new(name, element_type, 0));
+        self
+    }
+
+    /// Adds a fixed-length array member like `float fCov[15]`.
+    pub fn with_array(mut self, name: &str, element_type: ElementType, length: i32) -> Self {
+        self.elements
+            .push(StreamerElement::new(name, element_type, length));
+        self
+    }
+
+    /// The class checksum. For infos read from a file, this is the one
+    /// stored by ROOT, which resolves typedefs like `Float_t` and knows the
+    /// checksums of bases; otherwise it is computed as
+    /// `TStreamerInfo::GetCheckSum` does for the latest checksum version:
+    /// class name, then bases with their checksums, then member names, type
+    /// names and array dimensions.
+    pub fn checksum(&self) -> u32 {
+        self.stored_checksum
+            .unwrap_or_else(|| self.computed_checksum())
+    }
+
+    fn computed_checksum(&self) -> u32 {
+        fn add(id: u32, s: &str) -> u32 {
+            // Characters are added as (signed) `char`s.
+            s.bytes()
+                .fold(id, |id, c| id.wrapping_mul(3).wrapping_add(c as i8 as u32))
+        }
+
+        let mut id = add(0, &self.class_name);
+        for element in self.elements.iter().filter(|e| e.is_base()) {
+            id = add(id, &element.name);
+            if let ElementType::Base { checksum, .. } = element.element_type {
+                id = id.wrapping_mul(3).wrapping_add(checksum);
+            }
+        }
+        for element in self.elements.iter().filter(|e| !e.is_base()) {
+            id = add(id, &element.name);
+            id = add(id, &element.type_name);
+            if element.array_length > 0 {
+                id = id.wrapping_mul(3).wrapping_add(element.array_length as u32);
+            }
+        }
+        id
+    }
+
+    /// Reads a `TStreamerInfo`, from the start of its byte count. The
+    /// elements of infos found in `cache` are skipped.
+    pub(crate) fn read(
+        buf: &mut ReadBuffer,
+        cache: Option<&StreamerCache>,
+    ) -> Result<Self, RootIoError> {
+        let (_, end) = buf.read_version()?;
+        let (_, named_end) = buf.read_version()?;
+        buf.read_tobject()?;
+        let class_name = buf.read_string()?;
+        buf.skip_to(named_end)?;
+        let checksum = buf.read_u32::<BigEndian>()?;
+        let class_version = buf.read_i32::<BigEndian>()?;
+        let cached = cache.and_then(|cache| cache.get(&class_name, checksum));
+        let mut elements = vec![];
+        if let Some((array_class, array_end)) = buf.read_object_any()? {
+            if array_class != "TObjArray" {
+                return Err(RootIoError::InvalidFormatError);
+            }
+            buf.read_version()?;
+            buf.read_tobject()?;
+            buf.read_string()?;
+            let count = buf.read_i32::<BigEndian>()?;
+            // Lower bound.
+            buf.read_i32::<BigEndian>()?;
+            // The class tags of skipped elements are still read, as later
+            // infos may refer to them.
+            for _ in 0..count {
+                if let Some((element_class, element_end)) = buf.read_object_any()? {
+                    if cached.is_none() {
+                        elements.push(StreamerElement::read(buf, &element_class, element_end)?);
+                    }
+                    buf.skip_to(element_end)?;
+                }
+            }
+            buf.skip_to(array_end)?;
+        }
+        buf.skip_to(end)?;
+        if let Some(info) = cached {
+            return Ok(info);
+        }
+        let info = Self {
+            class_name,
+            class_version,
+            elements,
+            stored_checksum: Some(checksum),
+        };
+        if let Some(cache) = cache {
+            cache.insert(checksum, &info);
+        }
+        Ok(info)
+    }
+
+    pub(crate) fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+        buf.write_versioned(STREAMER_INFO_VERSION, |buf| {
+            buf.write_versioned(1, |buf| {
+                buf.write_tobject(0)?;
+                buf.write_string(&self.class_name)?;
+                buf.write_string("")
+            })?;
+            buf.write_u32::<BigEndian>(self.checksum())?;
+            buf.write_i32::<BigEndian>(self.class_version)?;
+            buf.write_object_any("TObjArray", |buf| {
+                buf.write_versioned(3, |buf| {
+                    buf.write_tobject(0)?;
+                    buf.write_string("")?;
+                    buf.write_i32::<BigEndian>(self.elements.len() as i32)?;
+                    // Lower bound.
+                    buf.write_i32::<BigEndian>(0)?;
+                    for element in &self.elements {
+                        buf.write_object_any(element.class_name(), |buf| element.stream(buf))?;
+                    }
+                    Ok(())
+                })
+            })
+        })
+    }
+}
+
+/// Streamer infos shared between files, e.g. those of a chain written by
+/// the same production, keyed by class name and the checksum stored in the
+/// file.
+#[derive(Debug, Default)]
+pub struct StreamerCache {
+    infos: Mutex<HashMap<(String, u32), StreamerInfo>>,
+}
+
+impl StreamerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct class layouts cached.
+    pub fn len(&self) -> usize {
+        self.infos().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, class_name: &str, checksum: u32) -> Option<StreamerInfo> {
+        self.infos()
+            .get(&(class_name.to_string(), checksum))
+            .cloned()
+    }
+
+    fn insert(&self, checksum: u32, info: &StreamerInfo) {
+        self.infos()
+            .insert((info.class_name.clone(), checksum), info.clone());
+    }
+
+    fn infos(&self) -> MutexGuard<'_, HashMap<(String, u32), StreamerInfo>> {
+        self.infos
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// The class layouts stored in the `StreamerInfo` record of the file.
+    pub fn streamer_infos(&self) -> Result<Vec<StreamerInfo>, RootIoError> {
+        self.read_streamer_infos(None)
+    }
+
+    /// Same as `streamer_infos`, but classes already in `cache` are not
+    /// parsed again, and new ones are added to it.
+    pub fn streamer_infos_cached(
+        &self,
+        cache: &StreamerCache,
+    ) -> Result<Vec<StreamerInfo>, RootIoError> {
+        self.read_streamer_infos(Some(cache))
+    }
+
+    fn read_streamer_infos(
+        &self,
+        cache: Option<&StreamerCache>,
+    ) -> Result<Vec<StreamerInfo>, RootIoError> {
+        if self.seek_info() == 0 {
+            return Ok(vec![]);
+        }
+        let key = RootKey::new(&mut *self.reader(), self.seek_info())?;
+        parse_streamer_list(&self.read_object(&key)?, key.key_len, cache)
+    }
+}
+
+/// Reads the infos of a decompressed `StreamerInfo` list stored under a key
+/// header of `key_len` bytes.
+pub(crate) fn parse_streamer_list(
+    data: &[u8],
+    key_len: u16,
+    cache: Option<&StreamerCache>,
+) -> Result<Vec<StreamerInfo>, RootIoError> {
+    let mut buf = ReadBuffer::new(data, key_len as u32);
+    // The list also holds the schema evolution rules.
+    read_list(&mut buf, |class_name, buf| match class_name {
+        "TStreamerInfo" => StreamerInfo::read(buf, cache).map(Some),
+        _ => Ok(None),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ElementType, StreamerCache, StreamerInfo};
+    use crate::{RootFile, RootFileWriter, RootIoError, RootKey, ToRoot, WriteBuffer};
+    use byteorder::{BigEndian, WriteBytesExt};
+    use std::io::Cursor;
+
+    struct Track {
+        pt: f32,
+        charge: i32,
+    }
+
+    impl ToRoot for Track {
+        fn class_name(&self) -> String {
+            "Track".to_string()
+        }
+
+        fn streamer_infos(&self) -> Vec<StreamerInfo> {
+            vec![StreamerInfo::new("Track", 1)
+                .with_member("pt", ElementType::Float)
+                .with_member("charge", ElementType::Int)]
+        }
+
+        fn stream(&self, buf: &mut WriteBuffer) -> Result<(), RootIoError> {
+            buf.write_versioned(1, |buf| {
+                buf.write_f32::<BigEndian>(self.pt)?;
+                buf.write_i32::<BigEndian>(self.charge)?;
+                Ok(())
+            })
+        }
+    }
+
+    fn count(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle)
+            .count()
+    }
+
+    #[test]
+    fn write_custom_class_info() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "tracks.root", "").unwrap();
+        let track = Track {
+            pt: 10.0,
+            charge: -1,
+        };
+        writer.write("track", &track).unwrap();
+        writer.write("track", &track).unwrap();
+        let bytes = writer.close().unwrap().into_inner();
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+
+        let key = RootKey::new(&mut Cursor::new(&bytes), root.seek_info()).unwrap();
+        assert_eq!(key.name, "StreamerInfo");
+        let list = root.read_object(&key).unwrap();
+        // One info, with the class of its second member referring back to
+        // the tag written for the first.
+        assert_eq!(count(&list, b"TStreamerInfo\0"), 1);
+        assert_eq!(count(&list, b"TStreamerBasicType\0"), 1);
+        assert_eq!(count(&list, b"\x05Track"), 1);
+    }
+
+    #[test]
+    fn read_opaque_objects() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "tracks.root", "").unwrap();
+        let track = Track {
+            pt: 10.0,
+            charge: -1,
+        };
+        writer.write("track", &track).unwrap();
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+
+        assert_eq!(root.streamer_infos().unwrap(), track.streamer_infos());
+        let object = root.read_opaque(root.key("track").unwrap()).unwrap();
+        assert_eq!(object.class_name, "Track");
+        assert_eq!(object.version, 1);
+        assert_eq!(&object.data[6..10], &10.0f32.to_be_bytes());
+        assert_eq!(
+            object.streamer_info.as_ref(),
+            track.streamer_infos().first()
+        );
+    }
+
+    #[test]
+    fn share_streamer_infos() {
+        let cache = StreamerCache::new();
+        for _ in 0..2 {
+            let mut writer = RootFileWriter::new(Cursor::new(vec![]), "tracks.root", "").unwrap();
+            let track = Track { pt: 1.0, charge: 1 };
+            writer.write("track", &track).unwrap();
+            let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+            assert_eq!(
+                root.streamer_infos_cached(&cache).unwrap(),
+                track.streamer_infos()
+            );
+            assert_eq!(cache.len(), 1);
+        }
+    }
+}
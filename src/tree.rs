@@ -0,0 +1,230 @@
+use crate::object::{unsupported_class, BYTE_COUNT_MASK};
+use crate::{DecodeContext, FromRoot, ReadBuffer, RootFile, RootIoError, RootKey};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Oldest `TTree` class version storing its totals as `Long64_t`.
+const MIN_TREE_VERSION: u16 = 16;
+
+/// Totals kept in a `TTree` object itself, so they can be read without
+/// touching any basket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeTotals {
+    /// `GetEntries`.
+    pub entries: i64,
+    /// `GetTotBytes`: the uncompressed size of the baskets.
+    pub tot_bytes: i64,
+    /// `GetZipBytes`: the size of the baskets on disk.
+    pub zip_bytes: i64,
+    /// Bytes written at the last `AutoSave`.
+    pub saved_bytes: i64,
+}
+
+impl FromRoot for TreeTotals {
+    fn class_name() -> String {
+        "TTree".to_string()
+    }
+
+    fn from_root(buf: &mut ReadBuffer, _: &DecodeContext) -> Result<Self, RootIoError> {
+        Self::parse(buf)
+    }
+}
+
+impl TreeTotals {
+    fn parse(mut reader: impl Read) -> Result<Self, RootIoError> {
+        let (_, version) = read_header(&mut reader)?;
+        if version < MIN_TREE_VERSION {
+            return Err(RootIoError::Unimplemented(format!(
+                "Reading TTree version {}",
+                version
+            )));
+        }
+        // The bases TNamed, TAttLine, TAttFill and TAttMarker.
+        for _ in 0..4 {
+            skip_object(&mut reader)?;
+        }
+        Ok(Self {
+            entries: reader.read_i64::<BigEndian>()?,
+            tot_bytes: reader.read_i64::<BigEndian>()?,
+            zip_bytes: reader.read_i64::<BigEndian>()?,
+            saved_bytes: reader.read_i64::<BigEndian>()?,
+        })
+    }
+}
+
+/// Reads the byte count and version of a streamed object, returning the
+/// length following the byte count and the version.
+fn read_header(reader: &mut impl Read) -> Result<(u64, u16), RootIoError> {
+    let count = reader.read_u32::<BigEndian>()?;
+    if count & BYTE_COUNT_MASK == 0 {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    let version = reader.read_u16::<BigEndian>()?;
+    Ok(((count & !BYTE_COUNT_MASK) as u64, version))
+}
+
+fn skip_object(reader: &mut impl Read) -> Result<(), RootIoError> {
+    let (len, _) = read_header(reader)?;
+    // The version was part of the counted bytes.
+    let skip = len.checked_sub(2).ok_or(RootIoError::InvalidFormatError)?;
+    if std::io::copy(&mut reader.take(skip), &mut std::io::sink())? != skip {
+        return Err(RootIoError::InvalidFormatError);
+    }
+    Ok(())
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// The totals of the tree stored under `key`. Only the start of the
+    /// tree object is read from the file and decompressed: its first
+    /// compression block, or its first MiB if it is not compressed.
+    /// `TNtuple`s and `TNtupleD`s, whose first base is `TTree`, are read
+    /// the same way.
+    pub fn tree_totals(&self, key: &RootKey) -> Result<TreeTotals, RootIoError> {
+        let mut reader = self.object_reader(key);
+        match key.class_name.as_str() {
+            "TTree" => {}
+            "TNtuple" | "TNtupleD" => {
+                read_header(&mut reader)?;
+            }
+            _ => {
+                return Err(unsupported_class(key).unwrap_or_else(|| {
+                    RootIoError::Unimplemented(format!("Reading tree totals of {}", key.class_name))
+                }))
+            }
+        }
+        TreeTotals::parse(reader)
+    }
+}
+
+/// Total number of entries of the tree `name` (`name;cycle` for a cycle
+/// other than the latest) over the files `paths`, as a `TChain` of them
+/// would have. Files are opened by `workers` threads, or one per core if 0,
+/// and only the key list and the start of the tree object of each is read.
+pub fn count_entries(
+    paths: &[impl AsRef<Path> + Sync],
+    name: &str,
+    workers: usize,
+) -> Result<i64, RootIoError> {
+    let entries = |path: &Path| -> Result<i64, RootIoError> {
+        let file = RootFile::open(path)?;
+        Ok(file.tree_totals(file.key(name)?)?.entries)
+    };
+
+    let workers = match workers {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let next = AtomicUsize::new(0);
+    let total = Mutex::new(Ok(0));
+    std::thread::scope(|scope| {
+        for _ in 0..workers.min(paths.len()) {
+            scope.spawn(|| {
+                let failed = || {
+                    total
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .is_err()
+                };
+                // No more files are opened once one has failed.
+                while !failed() {
+                    let path = match paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        Some(path) => path,
+                        None => break,
+                    };
+                    let result = entries(path.as_ref());
+                    let mut total = total
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    match (&mut *total, result) {
+                        (Ok(total), Ok(entries)) => *total += entries,
+                        (Ok(_), Err(err)) => *total = Err(err),
+                        (Err(_), _) => {}
+                    }
+                }
+            });
+        }
+    });
+    total
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_entries, TreeTotals};
+    use crate::{RootFile, RootFileWriter, WriteBuffer};
+    use byteorder::{BigEndian, WriteBytesExt};
+    use std::io::Write;
+
+    #[test]
+    fn read_tree_totals() {
+        let mut buf = WriteBuffer::new(0);
+        buf.write_versioned(20, |buf| {
+            buf.write_versioned(1, |buf| {
+                buf.write_tobject(0)?;
+                buf.write_string("events")?;
+                buf.write_string("")
+            })?;
+            // TAttLine, TAttFill and TAttMarker.
+            for len in [6, 4, 8] {
+                buf.write_versioned(2, |buf| Ok(buf.write_all(&vec![0; len])?))?;
+            }
+            for total in [1000i64, 80000, 20000, 19000] {
+                buf.write_i64::<BigEndian>(total)?;
+            }
+            // Members which are not read.
+            buf.write_i64::<BigEndian>(0)?;
+            Ok(())
+        })
+        .unwrap();
+        let tree = buf.into_inner();
+        let mut buf = WriteBuffer::new(0);
+        buf.write_versioned(2, |buf| {
+            buf.write_all(&tree)?;
+            Ok(buf.write_i32::<BigEndian>(1)?)
+        })
+        .unwrap();
+
+        let mut writer = RootFileWriter::in_memory("tree.root").unwrap();
+        writer.write_object("TTree", "events", "", &tree).unwrap();
+        writer.write_object("TObjString", "s", "", b"").unwrap();
+        writer
+            .write_object("TNtuple", "ntuple", "", &buf.into_inner())
+            .unwrap();
+        let bytes = writer.into_bytes().unwrap();
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+
+        let totals = root.tree_totals(&root.keys()[0]).unwrap();
+        assert_eq!(
+            totals,
+            TreeTotals {
+                entries: 1000,
+                tot_bytes: 80000,
+                zip_bytes: 20000,
+                saved_bytes: 19000,
+            }
+        );
+        assert!(root.tree_totals(&root.keys()[1]).is_err());
+        assert_eq!(root.tree_totals(&root.keys()[2]).unwrap(), totals);
+
+        let paths: Vec<_> = (0..3)
+            .map(|i| {
+                let path = std::env::temp_dir().join(format!(
+                    "root_reader_chain_{}_{}.root",
+                    std::process::id(),
+                    i
+                ));
+                std::fs::write(&path, &bytes).unwrap();
+                path
+            })
+            .collect();
+        assert_eq!(count_entries(&paths, "events", 2).unwrap(), 3000);
+        assert!(count_entries(&paths, "missing", 2).is_err());
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,1547 @@
+use crate::directory::DIRECTORY_LEN;
+use crate::histogram::{add_histograms, is_histogram};
+use crate::internal::*;
+use crate::object::{append_list, write_list, WriteBuffer};
+use crate::streamer::parse_streamer_list;
+use crate::{
+    RootDirectory, RootFile, RootIoError, RootKey, StreamerInfo, ToRoot, START_BIG_FILE,
+    VER_THRESHOLD, VER_THRESHOLD_KEY,
+};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BEGIN: u64 = 100;
+/// `TFile::GetVersion` of the ROOT release whose layout is written (6.22/06).
+const FILE_VERSION: u32 = 62206;
+const KEY_VERSION: u16 = 4;
+const DIRECTORY_VERSION: u16 = 5;
+const FREE_VERSION: u16 = 1;
+/// Objects up to this size are never compressed, as in `TKey::TKey`.
+const MIN_COMPRESS_LEN: usize = 256;
+/// Sizes in a compression block header are 24 bit (`kMAXZIPBUF`).
+pub(crate) const MAX_BLOCK_LEN: usize = 0xffffff;
+
+/// Compression applied to objects written by `RootFileWriter`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    /// zlib with the given level (1-9).
+    Zlib(u32),
+}
+
+impl Compression {
+    /// The compression for an `fCompress` value read from a file. Algorithms
+    /// this crate cannot write fall back to zlib at the same level.
+    fn from_setting(setting: u32) -> Self {
+        match setting % 100 {
+            0 => Compression::None,
+            level => Compression::Zlib(level),
+        }
+    }
+
+    /// The `fCompress` value ROOT stores for this setting.
+    pub fn setting(self) -> u32 {
+        match self {
+            Compression::None => 0,
+            Compression::Zlib(level) => 100 + level,
+        }
+    }
+
+    /// Returns the payload as compression blocks of at most `block_len`
+    /// bytes each, as `TKey` does, or `None` if it should be stored as is.
+    fn compress(self, payload: &[u8], block_len: usize) -> Result<Option<Vec<u8>>, RootIoError> {
+        let level = match self {
+            Compression::None | Compression::Zlib(0) => return Ok(None),
+            Compression::Zlib(level) => level,
+        };
+        if payload.len() <= MIN_COMPRESS_LEN {
+            return Ok(None);
+        }
+
+        let mut blocks = vec![];
+        for chunk in payload.chunks(block_len) {
+            let zlib = zlib(chunk, level)?;
+            if zlib.len() > MAX_BLOCK_LEN {
+                return Ok(None);
+            }
+            blocks.extend_from_slice(b"ZL\x08");
+            blocks.extend_from_slice(&(zlib.len() as u32).to_le_bytes()[..3]);
+            blocks.extend_from_slice(&(chunk.len() as u32).to_le_bytes()[..3]);
+            blocks.extend_from_slice(&zlib);
+        }
+        if blocks.len() >= payload.len() {
+            return Ok(None);
+        }
+        Ok(Some(blocks))
+    }
+}
+
+#[cfg(feature = "zlib")]
+fn zlib(data: &[u8], level: u32) -> Result<Vec<u8>, RootIoError> {
+    let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::new(level));
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "zlib"))]
+fn zlib(_: &[u8], _: u32) -> Result<Vec<u8>, RootIoError> {
+    Err(RootIoError::Unimplemented(
+        "zlib compression without the zlib feature".to_string(),
+    ))
+}
+
+/// Writes a new ROOT file, or appends to an existing one.
+///
+/// Objects are appended as they are written; the key list, streamer info,
+/// free list and header are only written by `close`, so a writer dropped
+/// without closing leaves a new file which needs recovery, and an updated
+/// file as it was before.
+#[derive(Debug)]
+pub struct RootFileWriter<W: Write + Seek> {
+    writer: W,
+    name: String,
+    title: String,
+    end: u64,
+    /// End of an updated file when it was opened, below which nothing is
+    /// overwritten.
+    opened_end: u64,
+    seek_free: u64,
+    nbytes_free: u32,
+    nfree: u32,
+    nbytes_name: u32,
+    compression: Compression,
+    /// Object bytes per compression block, smaller than `MAX_BLOCK_LEN` in
+    /// tests only.
+    block_len: usize,
+    seek_info: u64,
+    nbytes_info: u32,
+    uuid: [u8; 18],
+    directory: RootDirectory,
+    keys: Vec<RootKey>,
+    streamer_infos: Vec<StreamerInfo>,
+    /// Free segments as inclusive ranges, without the one after the end.
+    free: Vec<(u64, u64)>,
+    /// Streamer info list of an updated file, with its key header length.
+    streamer_list: Option<(Vec<u8>, u16)>,
+    /// Subdirectories copied from other files, whose key lists are written
+    /// by `close`.
+    subdirectories: Vec<Subdirectory>,
+    /// Decompressed payloads of the histograms copied by `merge`, by the
+    /// position of their key, for the same histograms of later inputs to be
+    /// added to.
+    histograms: HashMap<u64, Vec<u8>>,
+    /// The keys, in the file being copied from, of the directories
+    /// `copy_directory` is copying.
+    copying: Vec<u64>,
+}
+
+/// A subdirectory written by `RootFileWriter`.
+#[derive(Debug)]
+struct Subdirectory {
+    key: RootKey,
+    record: RootDirectory,
+    keys: Vec<RootKey>,
+}
+
+impl RootFileWriter<File> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, RootIoError> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|source| RootIoError::OpenError {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::new(file, &path.to_string_lossy(), "")
+    }
+
+    /// Opens the existing file at `path` for appending, like ROOT's
+    /// `UPDATE` mode.
+    pub fn open_update(path: impl AsRef<Path>) -> Result<Self, RootIoError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|source| RootIoError::OpenError {
+                path: path.to_owned(),
+                source,
+            })?;
+        Self::update(file)
+    }
+}
+
+impl RootFileWriter<Cursor<Vec<u8>>> {
+    /// Starts a file kept in memory, like `TMemFile`, e.g. for tests or to
+    /// serve generated files without touching the disk.
+    pub fn in_memory(name: &str) -> Result<Self, RootIoError> {
+        Self::new(Cursor::new(vec![]), name, "")
+    }
+
+    /// Closes the file and returns its contents.
+    pub fn into_bytes(self) -> Result<Vec<u8>, RootIoError> {
+        Ok(self.close()?.into_inner())
+    }
+}
+
+impl<W: Read + Write + Seek> RootFileWriter<W> {
+    /// Continues a closed file. New keys are appended after its end, and
+    /// `close` replaces its key list, streamer info and free list, whose
+    /// old records become free space.
+    pub fn update(inner: W) -> Result<Self, RootIoError> {
+        let file = RootFile::new(inner)?;
+        let directory = match file.directory() {
+            Some(directory) if file.begin() == BEGIN => directory.clone(),
+            Some(_) => {
+                return Err(RootIoError::Unimplemented(format!(
+                    "Updating a file starting at {}",
+                    file.begin()
+                )))
+            }
+            None => {
+                return Err(RootIoError::Unimplemented(
+                    "Updating a file which needs recovery".to_string(),
+                ))
+            }
+        };
+
+        let streamer_list = read_streamer_list(&file)?;
+        let mut free = vec![];
+        if file.seek_free() > 0 {
+            let key = RootKey::new(&mut *file.reader(), file.seek_free())?;
+            let list = file.read_object(&key)?;
+            let mut reader = Cursor::new(&list);
+            while (reader.position() as usize) < list.len() {
+                let version = reader.read_u16::<BigEndian>()?;
+                read_u64_val!(version > VER_THRESHOLD_KEY, &mut reader, first, last);
+                if first < file.end() {
+                    free.push((first, last));
+                }
+            }
+        }
+
+        let mut writer = Self {
+            name: String::new(),
+            title: String::new(),
+            end: file.end(),
+            opened_end: file.end(),
+            seek_free: file.seek_free(),
+            nbytes_free: file.nbytes_free(),
+            nfree: file.nfree(),
+            nbytes_name: file.nbytes_name(),
+            compression: Compression::from_setting(file.compress()),
+            block_len: MAX_BLOCK_LEN,
+            seek_info: file.seek_info(),
+            nbytes_info: file.nbytes_info(),
+            uuid: *file.uuid(),
+            directory,
+            keys: file.keys().to_vec(),
+            streamer_infos: vec![],
+            free,
+            streamer_list,
+            subdirectories: vec![],
+            histograms: HashMap::new(),
+            copying: vec![],
+            writer: file.into_inner(),
+        };
+        let key = RootKey::new(&mut writer.writer, BEGIN)?;
+        writer.name = key.name;
+        writer.title = key.title;
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> RootFileWriter<W> {
+    /// Starts a file named `name` (as stored in the file, usually its path).
+    pub fn new(writer: W, name: &str, title: &str) -> Result<Self, RootIoError> {
+        let now = datime_now();
+        let uuid = new_uuid();
+
+        // The top directory is stored as the object of the first key:
+        // the name and title of the file followed by the directory record.
+        let name_len = string_len(name) + string_len(title);
+        let key_len = RootKey::header_len(KEY_VERSION, "TFile", name, title);
+        let obj_len = (name_len + DIRECTORY_LEN) as u32;
+        let nbytes_name = key_len as u32 + name_len as u32;
+        let key = RootKey {
+            begin: BEGIN,
+            meta_data: vec![],
+            obj_begin: BEGIN + key_len as u64,
+            nbytes: key_len as u32 + obj_len,
+            version: KEY_VERSION,
+            obj_len,
+            datime: now,
+            key_len,
+            cycle: 1,
+            seek_key: BEGIN,
+            seek_pdir: 0,
+            class_name: "TFile".to_string(),
+            name: name.to_string(),
+            title: title.to_string(),
+        };
+        let directory = RootDirectory {
+            version: DIRECTORY_VERSION,
+            ctime: now,
+            mtime: now,
+            nbytes_keys: 0,
+            nbytes_name,
+            seek_dir: BEGIN,
+            seek_parent: 0,
+            seek_keys: 0,
+            uuid,
+        };
+
+        let mut file = Self {
+            writer,
+            name: name.to_string(),
+            title: title.to_string(),
+            end: key.next_position(),
+            opened_end: 0,
+            seek_free: 0,
+            nbytes_free: 0,
+            nfree: 0,
+            nbytes_name,
+            compression: Compression::None,
+            block_len: MAX_BLOCK_LEN,
+            seek_info: 0,
+            nbytes_info: 0,
+            uuid,
+            directory,
+            keys: vec![],
+            streamer_infos: vec![],
+            free: vec![],
+            streamer_list: None,
+            subdirectories: vec![],
+            histograms: HashMap::new(),
+            copying: vec![],
+        };
+        file.write_file_header()?;
+        key.write_header(&mut file.writer)?;
+        write_string(&mut file.writer, name)?;
+        write_string(&mut file.writer, title)?;
+        file.directory.write(&mut file.writer)?;
+        Ok(file)
+    }
+
+    /// Sets the compression used for objects written from now on.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Splits objects in blocks of `len` bytes, so tests get several blocks
+    /// out of small payloads.
+    #[cfg(all(test, feature = "zlib"))]
+    pub(crate) fn set_block_len(&mut self, len: usize) {
+        self.block_len = len;
+    }
+
+    pub fn keys(&self) -> &[RootKey] {
+        &self.keys
+    }
+
+    /// Stores `payload`, the streamed bytes of an object of class
+    /// `class_name`, under the key `name`. Returns the cycle of the new key.
+    pub fn write_object(
+        &mut self,
+        class_name: &str,
+        name: &str,
+        title: &str,
+        payload: &[u8],
+    ) -> Result<u16, RootIoError> {
+        self.write_new_key(class_name, name, title, payload, self.compression)
+    }
+
+    fn write_new_key(
+        &mut self,
+        class_name: &str,
+        name: &str,
+        title: &str,
+        payload: &[u8],
+        compression: Compression,
+    ) -> Result<u16, RootIoError> {
+        let cycle = self.next_cycle(name);
+        let key = self.write_key(class_name, name, title, cycle, payload, compression)?;
+        self.keys.push(key);
+        Ok(cycle)
+    }
+
+    fn next_cycle(&self, name: &str) -> u16 {
+        self.keys
+            .iter()
+            .filter(|key| key.name == name)
+            .map(|key| key.cycle)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Streams `object` into a new key `name`. Returns the cycle of the key.
+    pub fn write(&mut self, name: &str, object: &impl ToRoot) -> Result<u16, RootIoError> {
+        self.write_compressed(name, object, self.compression)
+    }
+
+    /// Same as `write`, but overrides the file compression for this object,
+    /// e.g. to store small index objects uncompressed.
+    pub fn write_compressed(
+        &mut self,
+        name: &str,
+        object: &impl ToRoot,
+        compression: Compression,
+    ) -> Result<u16, RootIoError> {
+        let class_name = object.class_name();
+        let mut buf = self.object_buffer(&class_name, name, object.title());
+        object.stream(&mut buf)?;
+        for info in object.streamer_infos() {
+            self.add_streamer_info(info);
+        }
+        let payload = buf.into_inner();
+        self.write_new_key(&class_name, name, object.title(), &payload, compression)
+    }
+
+    /// Removes cycle `cycle` of the key `name`, or all of its cycles if
+    /// `cycle` is `None`, and frees their space. Returns the number of keys
+    /// removed.
+    pub fn delete(&mut self, name: &str, cycle: Option<u16>) -> usize {
+        let (deleted, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.keys)
+            .into_iter()
+            .partition(|key| key.name == name && cycle.is_none_or(|c| key.cycle == c));
+        self.keys = kept;
+        for key in &deleted {
+            self.free_key(key);
+        }
+        deleted.len()
+    }
+
+    /// Frees the record of `key` and, if it is a subdirectory copied here,
+    /// those of its keys, whose key list is then not written by `close`.
+    fn free_key(&mut self, key: &RootKey) {
+        self.free_segment(key.seek_key, key.seek_key + key.nbytes as u64 - 1);
+        self.histograms.remove(&key.seek_key);
+        let found = self
+            .subdirectories
+            .iter()
+            .position(|dir| dir.key.seek_key == key.seek_key);
+        if let Some(index) = found {
+            for child in self.subdirectories.remove(index).keys {
+                self.free_key(&child);
+            }
+        }
+    }
+
+    /// Adds the objects of `file` to this file, like `hadd` does with each
+    /// of its inputs: the latest cycle of each key is copied as stored,
+    /// directories of the same path are merged, and histograms of a path
+    /// already written (`TH1F`, `TH2D` and the like) are added to it. Other
+    /// objects of a path already written are kept from the first file.
+    /// Streamer infos of classes and versions not seen in earlier files are
+    /// added.
+    ///
+    /// Trees and profiles of a path already written are not chained or
+    /// added, as this crate decodes neither; they are rejected with
+    /// `Unimplemented` before anything of `file` is written. The histograms
+    /// copied are kept in memory until the writer is closed.
+    ///
+    /// With `compression`, objects are recompressed instead of copied as
+    /// stored. Added histograms are always compressed anew.
+    pub fn merge<T: Read + Seek>(
+        &mut self,
+        file: &RootFile<T>,
+        compression: Option<Compression>,
+    ) -> Result<(), RootIoError> {
+        check_unfiltered(file, "Merging")?;
+        let mut sums = HashMap::new();
+        self.check_merge(file, file.keys(), &self.keys, &mut sums)?;
+        match self.streamer_list {
+            Some(_) => {
+                let infos = self.new_streamer_infos(file)?;
+                self.streamer_infos.extend(infos);
+            }
+            None => self.streamer_list = read_streamer_list(file)?,
+        }
+        let mut keys = std::mem::take(&mut self.keys);
+        let merged = self.merge_keys(file, file.keys(), &mut keys, BEGIN, compression, &mut sums);
+        self.keys = keys;
+        merged
+    }
+
+    /// Checks that the keys `source` of `file` can be merged into `dest`,
+    /// and adds the histograms of both, by the position of the key in
+    /// `dest`, to `sums`.
+    fn check_merge<T: Read + Seek>(
+        &self,
+        file: &RootFile<T>,
+        source: &[RootKey],
+        dest: &[RootKey],
+        sums: &mut HashMap<u64, Vec<u8>>,
+    ) -> Result<(), RootIoError> {
+        for key in latest_cycles(source) {
+            let existing = match dest.iter().find(|k| k.name == key.name) {
+                Some(existing) => existing,
+                None => continue,
+            };
+            if is_directory(&existing.class_name) && is_directory(&key.class_name) {
+                let dir = self
+                    .subdirectories
+                    .iter()
+                    .find(|dir| dir.key.seek_key == existing.seek_key)
+                    .ok_or_else(|| {
+                        RootIoError::Unimplemented(format!(
+                            "Merging into the directory {} of an updated file",
+                            key.name
+                        ))
+                    })?;
+                self.check_merge(file, &file.directory_keys(key)?, &dir.keys, sums)?;
+            } else if is_summed(&existing.class_name) || is_summed(&key.class_name) {
+                let mut sum = match self.histograms.get(&existing.seek_key) {
+                    Some(sum) if existing.class_name == key.class_name => sum.clone(),
+                    _ => {
+                        return Err(RootIoError::Unimplemented(format!(
+                            "Merging {} objects ({})",
+                            key.class_name, key.name
+                        )))
+                    }
+                };
+                add_histograms(&key.class_name, &mut sum, &file.read_object(key)?)?;
+                sums.insert(existing.seek_key, sum);
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges the keys `source` of `file` into `dest`, the keys of the
+    /// directory at `seek_pdir`, replacing the histograms found in `sums`
+    /// by their sums.
+    fn merge_keys<T: Read + Seek>(
+        &mut self,
+        file: &RootFile<T>,
+        source: &[RootKey],
+        dest: &mut Vec<RootKey>,
+        seek_pdir: u64,
+        compression: Option<Compression>,
+        sums: &mut HashMap<u64, Vec<u8>>,
+    ) -> Result<(), RootIoError> {
+        for key in latest_cycles(source) {
+            let index = match dest.iter().position(|k| k.name == key.name) {
+                Some(index) => index,
+                None => {
+                    let mut copy = key.clone();
+                    copy.seek_pdir = seek_pdir;
+                    let copy = self.copy_record(file, key, copy, compression, true)?;
+                    dest.push(copy);
+                    continue;
+                }
+            };
+            let seek_key = dest[index].seek_key;
+            if is_directory(&dest[index].class_name) && is_directory(&key.class_name) {
+                let subdirectory = self
+                    .subdirectories
+                    .iter()
+                    .position(|dir| dir.key.seek_key == seek_key)
+                    .ok_or(RootIoError::InvalidFormatError)?;
+                let mut keys = std::mem::take(&mut self.subdirectories[subdirectory].keys);
+                let merged = file.directory_keys(key).and_then(|source| {
+                    self.merge_keys(file, &source, &mut keys, seek_key, compression, sums)
+                });
+                self.subdirectories[subdirectory].keys = keys;
+                merged?;
+            } else if let Some(sum) = sums.remove(&seek_key) {
+                dest[index] = self.replace_histogram(&dest[index], sum, compression)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `sum`, the new payload of the histogram of `old`, under a new
+    /// key in its place and frees the old record.
+    fn replace_histogram(
+        &mut self,
+        old: &RootKey,
+        sum: Vec<u8>,
+        compression: Option<Compression>,
+    ) -> Result<RootKey, RootIoError> {
+        // The class tags in the payload are offsets from the key start.
+        let key_len =
+            RootKey::header_len(self.key_version(), &old.class_name, &old.name, &old.title);
+        if key_len != old.key_len {
+            return Err(RootIoError::Unimplemented(format!(
+                "Adding the {} {} under a header of another length",
+                old.class_name, old.name
+            )));
+        }
+        let compressed = compression
+            .unwrap_or(self.compression)
+            .compress(&sum, self.block_len)?;
+        let mut key = new_key(&old.class_name, &old.name, &old.title, old.cycle);
+        key.obj_len = sum.len() as u32;
+        key.seek_pdir = old.seek_pdir;
+        let key = self.append_record(key, compressed.as_deref().unwrap_or(&sum))?;
+        self.free_segment(old.seek_key, old.seek_key + old.nbytes as u64 - 1);
+        self.histograms.remove(&old.seek_key);
+        self.histograms.insert(key.seek_key, sum);
+        Ok(key)
+    }
+
+    /// The streamer infos of `file` for classes and versions not recorded
+    /// yet.
+    fn new_streamer_infos<T: Read + Seek>(
+        &self,
+        file: &RootFile<T>,
+    ) -> Result<Vec<StreamerInfo>, RootIoError> {
+        let listed = match &self.streamer_list {
+            Some((list, key_len)) => parse_streamer_list(list, *key_len, None)?,
+            None => vec![],
+        };
+        let mut infos: Vec<StreamerInfo> = vec![];
+        for info in file.streamer_infos()? {
+            let known = listed
+                .iter()
+                .chain(&self.streamer_infos)
+                .chain(&infos)
+                .any(|i| i.class_name == info.class_name && i.class_version == info.class_version);
+            if !known {
+                infos.push(info);
+            }
+        }
+        Ok(infos)
+    }
+
+    /// Records the layout of a class in the streamer info written on close.
+    /// A later info for the same class and version replaces an earlier one.
+    pub fn add_streamer_info(&mut self, info: StreamerInfo) {
+        match self
+            .streamer_infos
+            .iter_mut()
+            .find(|i| i.class_name == info.class_name && i.class_version == info.class_version)
+        {
+            Some(existing) => *existing = info,
+            None => self.streamer_infos.push(info),
+        }
+    }
+
+    /// Writes the streamer info, key list, free list and header.
+    pub fn close(mut self) -> Result<W, RootIoError> {
+        // Records replaced below, if the file is being updated.
+        for (seek, nbytes) in [
+            (self.seek_info, self.nbytes_info),
+            (self.directory.seek_keys, self.directory.nbytes_keys),
+            (self.seek_free, self.nbytes_free),
+        ] {
+            if seek > 0 {
+                self.free_segment(seek, seek + nbytes as u64 - 1);
+            }
+        }
+
+        let (info_name, info_title) = ("StreamerInfo", "Doubly linked list");
+        let mut buf = self.object_buffer("TList", info_name, info_title);
+        match self.streamer_list.take() {
+            // Class tags in the old list are offsets from the key start.
+            Some((_, key_len)) if key_len as u32 != buf.displacement() => {
+                return Err(RootIoError::Unimplemented(
+                    "Rewriting streamer info across the 2 GB limit".to_string(),
+                ))
+            }
+            Some((list, _)) => append_list(
+                &mut buf,
+                &list,
+                &self.streamer_infos,
+                "TStreamerInfo",
+                |info, buf| info.stream(buf),
+            )?,
+            None => write_list(
+                &mut buf,
+                &self.streamer_infos,
+                "TStreamerInfo",
+                |info, buf| info.stream(buf),
+            )?,
+        }
+        let info = self.write_key(
+            "TList",
+            info_name,
+            info_title,
+            1,
+            &buf.into_inner(),
+            self.compression,
+        )?;
+        self.seek_info = info.seek_key;
+        self.nbytes_info = info.nbytes;
+
+        for dir in std::mem::take(&mut self.subdirectories) {
+            let key_list = key_list(&dir.keys)?;
+            let mut key = new_key(&dir.key.class_name, &dir.key.name, &dir.key.title, 1);
+            key.obj_len = key_list.len() as u32;
+            key.seek_pdir = dir.key.seek_key;
+            let keys = self.append_record(key, &key_list)?;
+            let mut record = dir.record;
+            record.seek_keys = keys.seek_key;
+            record.nbytes_keys = keys.nbytes;
+            if record
+                .seek_dir
+                .max(record.seek_parent)
+                .max(record.seek_keys)
+                > START_BIG_FILE
+            {
+                record.version = DIRECTORY_VERSION + VER_THRESHOLD_KEY;
+            }
+            self.writer.seek(SeekFrom::Start(dir.key.obj_begin))?;
+            record.write(&mut self.writer)?;
+        }
+
+        let key_list = key_list(&self.keys)?;
+        let (name, title) = (self.name.clone(), self.title.clone());
+        let keys = self.write_key("TFile", &name, &title, 1, &key_list, Compression::None)?;
+        self.directory.seek_keys = keys.seek_key;
+        self.directory.nbytes_keys = keys.nbytes;
+
+        // The free list ends with the unused space after the file, which
+        // starts right after the free list itself.
+        let mut free = vec![];
+        for &(first, last) in &self.free {
+            write_free_segment(&mut free, first, last)?;
+        }
+        let key_len = RootKey::header_len(self.key_version(), "TFile", &name, &title);
+        let free_len = if self.end > START_BIG_FILE { 18 } else { 10 };
+        let first = self.end + key_len as u64 + free.len() as u64 + free_len;
+        let last = if first > START_BIG_FILE {
+            i64::MAX as u64
+        } else {
+            START_BIG_FILE
+        };
+        write_free_segment(&mut free, first, last)?;
+        let free = self.write_key("TFile", &name, &title, 1, &free, Compression::None)?;
+        self.seek_free = free.seek_key;
+        self.nbytes_free = free.nbytes;
+        self.nfree = self.free.len() as u32 + 1;
+
+        // Free segments start with their negated length, so that a record
+        // scan can skip them. They are only marked now, as the old key list
+        // may still refer to them until the header is rewritten.
+        for &(first, last) in &self.free {
+            self.writer.seek(SeekFrom::Start(first))?;
+            self.writer
+                .write_i32::<BigEndian>(-((last - first + 1).min(i32::MAX as u64) as i32))?;
+        }
+
+        self.directory.mtime = datime_now();
+        self.directory.version = if self.directory.seek_keys > START_BIG_FILE {
+            DIRECTORY_VERSION + VER_THRESHOLD_KEY
+        } else {
+            DIRECTORY_VERSION
+        };
+        self.writer
+            .seek(SeekFrom::Start(BEGIN + self.nbytes_name as u64))?;
+        self.directory.write(&mut self.writer)?;
+        self.write_file_header()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Adds the bytes `first..=last` to the free list, as `TFile::MakeFree`
+    /// does; the file shrinks instead if they were at its end, but not below
+    /// the end of an updated file, whose old records must stay intact until
+    /// the header is rewritten.
+    fn free_segment(&mut self, first: u64, last: u64) {
+        let (mut first, mut last) = (first, last);
+        self.free.retain(|&(f, l)| {
+            let adjacent = l + 1 >= first && f <= last + 1;
+            if adjacent {
+                first = first.min(f);
+                last = last.max(l);
+            }
+            !adjacent
+        });
+        if last + 1 == self.end && first >= self.opened_end {
+            self.end = first;
+            return;
+        }
+        if last + 1 == self.end && last >= self.opened_end {
+            self.end = self.opened_end;
+            last = self.opened_end - 1;
+        }
+        let pos = self.free.partition_point(|&(f, _)| f < first);
+        self.free.insert(pos, (first, last));
+    }
+
+    /// A buffer for the object of the next key, which knows the length of
+    /// the key header it will follow.
+    fn object_buffer(&self, class_name: &str, name: &str, title: &str) -> WriteBuffer {
+        let key_len = RootKey::header_len(self.key_version(), class_name, name, title);
+        WriteBuffer::new(key_len as u32)
+    }
+
+    fn key_version(&self) -> u16 {
+        if self.end > START_BIG_FILE {
+            KEY_VERSION + VER_THRESHOLD_KEY
+        } else {
+            KEY_VERSION
+        }
+    }
+
+    fn write_key(
+        &mut self,
+        class_name: &str,
+        name: &str,
+        title: &str,
+        cycle: u16,
+        payload: &[u8],
+        compression: Compression,
+    ) -> Result<RootKey, RootIoError> {
+        let compressed = compression.compress(payload, self.block_len)?;
+        let mut key = new_key(class_name, name, title, cycle);
+        key.obj_len = payload.len() as u32;
+        self.append_record(key, compressed.as_deref().unwrap_or(payload))
+    }
+
+    /// Copies the object `path` (`name` or `name;cycle`, the latest cycle
+    /// by default) of `file` into a new key `new_name`. The object is copied
+    /// as stored unless `compression` is given, in which case it is
+    /// recompressed. Returns the cycle of the new key.
+    pub fn copy_object<T: Read + Seek>(
+        &mut self,
+        file: &RootFile<T>,
+        path: &str,
+        new_name: &str,
+        compression: Option<Compression>,
+    ) -> Result<u16, RootIoError> {
+        let key = file.key(path)?;
+        let mut copy = key.clone();
+        copy.name = new_name.to_string();
+        copy.cycle = self.next_cycle(new_name);
+        copy.seek_pdir = BEGIN;
+        let copy = self.copy_record(file, key, copy, compression, false)?;
+        let cycle = copy.cycle;
+        self.keys.push(copy);
+        Ok(cycle)
+    }
+
+    /// Stores the object of `key` in `file` under `copy`, in the directory
+    /// at `copy.seek_pdir`, and returns its new key. The latest cycles of
+    /// the keys of a subdirectory are copied along with it. With
+    /// `keep_histograms`, the payloads of histograms are kept for `merge`.
+    fn copy_record<T: Read + Seek>(
+        &mut self,
+        file: &RootFile<T>,
+        key: &RootKey,
+        copy: RootKey,
+        compression: Option<Compression>,
+        keep_histograms: bool,
+    ) -> Result<RootKey, RootIoError> {
+        if is_directory(&key.class_name) {
+            return self.copy_directory(file, key, copy, compression, keep_histograms);
+        }
+        let raw = file.read_raw_object(key)?;
+        let key_len = RootKey::header_len(
+            self.key_version(),
+            &copy.class_name,
+            &copy.name,
+            &copy.title,
+        );
+        // Class tags are offsets from the start of the key, so they would
+        // have to be rewritten for a header of a different length. Only
+        // objects of classes known to stream no other objects are moved.
+        if key_len != key.key_len && !has_no_class_tags(&key.class_name) {
+            return Err(RootIoError::Unimplemented(format!(
+                "Copying the {} {} under a header of another length",
+                key.class_name, key.name
+            )));
+        }
+        let payload = if compression.is_some() {
+            let mut payload = Vec::with_capacity(key.obj_len as usize);
+            key.decompress(&raw)?.read_to_end(&mut payload)?;
+            payload
+        } else {
+            vec![]
+        };
+        let compressed = match compression {
+            Some(compression) => compression.compress(&payload, self.block_len)?,
+            None => None,
+        };
+        let data = match (compression, &compressed) {
+            (_, Some(compressed)) => compressed,
+            (Some(_), None) => &payload,
+            (None, None) => &raw,
+        };
+        let copy = self.append_record(copy, data)?;
+        if keep_histograms && is_histogram(&key.class_name) {
+            let payload = match compression {
+                Some(_) => payload,
+                None => file.read_object(key)?,
+            };
+            self.histograms.insert(copy.seek_key, payload);
+        }
+        Ok(copy)
+    }
+
+    /// Copies the subdirectory of `key` in `file`, whose key list is written
+    /// by `close`.
+    fn copy_directory<T: Read + Seek>(
+        &mut self,
+        file: &RootFile<T>,
+        key: &RootKey,
+        mut copy: RootKey,
+        compression: Option<Compression>,
+        keep_histograms: bool,
+    ) -> Result<RootKey, RootIoError> {
+        let source = RootDirectory::new(&mut Cursor::new(file.read_object(key)?))?;
+        let children = file.subdirectory_keys(key, &self.copying)?;
+        let mut record = RootDirectory {
+            version: DIRECTORY_VERSION,
+            nbytes_keys: 0,
+            nbytes_name: 0,
+            seek_dir: 0,
+            seek_parent: copy.seek_pdir,
+            seek_keys: 0,
+            ..source
+        };
+        let mut data = vec![];
+        record.write(&mut data)?;
+        copy.obj_len = data.len() as u32;
+        let copy = self.append_record(copy, &data)?;
+        record.seek_dir = copy.seek_key;
+        record.nbytes_name = copy.key_len as u32;
+
+        let mut keys = vec![];
+        self.copying.push(key.seek_key);
+        let copied: Result<(), RootIoError> = latest_cycles(&children).try_for_each(|child| {
+            let mut child_copy = child.clone();
+            child_copy.seek_pdir = copy.seek_key;
+            keys.push(self.copy_record(file, child, child_copy, compression, keep_histograms)?);
+            Ok(())
+        });
+        self.copying.pop();
+        copied?;
+        self.subdirectories.push(Subdirectory {
+            key: copy.clone(),
+            record,
+            keys,
+        });
+        Ok(copy)
+    }
+
+    /// Appends `data`, the object as stored, under `key`, whose position and
+    /// header length are set here.
+    fn append_record(&mut self, mut key: RootKey, data: &[u8]) -> Result<RootKey, RootIoError> {
+        key.version = self.key_version();
+        key.key_len = RootKey::header_len(key.version, &key.class_name, &key.name, &key.title);
+        key.begin = self.end;
+        key.seek_key = self.end;
+        key.obj_begin = self.end + key.key_len as u64;
+        key.nbytes = key.key_len as u32 + data.len() as u32;
+        key.meta_data = vec![];
+        self.writer.seek(SeekFrom::Start(key.seek_key))?;
+        key.write_header(&mut self.writer)?;
+        self.writer.write_all(data)?;
+        self.end = key.next_position();
+        Ok(key)
+    }
+
+    fn write_file_header(&mut self) -> Result<(), RootIoError> {
+        let large = self.end > START_BIG_FILE;
+        let w = &mut self.writer;
+        w.seek(SeekFrom::Start(0))?;
+        w.write_all(b"root")?;
+        w.write_u32::<BigEndian>(if large {
+            FILE_VERSION + VER_THRESHOLD
+        } else {
+            FILE_VERSION
+        })?;
+        w.write_u32::<BigEndian>(BEGIN as u32)?;
+        write_as_u64(large, w, self.end)?;
+        write_as_u64(large, w, self.seek_free)?;
+        w.write_u32::<BigEndian>(self.nbytes_free)?;
+        w.write_u32::<BigEndian>(self.nfree)?;
+        w.write_u32::<BigEndian>(self.nbytes_name)?;
+        w.write_u8(if large { 8 } else { 4 })?;
+        w.write_u32::<BigEndian>(self.compression.setting())?;
+        write_as_u64(large, w, self.seek_info)?;
+        w.write_u32::<BigEndian>(self.nbytes_info)?;
+        w.write_all(&self.uuid)?;
+        let written = w.stream_position()?;
+        w.write_all(&vec![0; (BEGIN - written) as usize])?;
+        Ok(())
+    }
+}
+
+impl<T: Read + Seek> RootFile<T> {
+    /// Writes a copy of the file without its free segments and with only the
+    /// latest cycle of each key, like `hadd -f` does. Subdirectories are
+    /// copied the same way, recursively. Objects are copied as stored,
+    /// without recompressing them.
+    pub fn compact<W: Write + Seek>(&self, output: W) -> Result<W, RootIoError> {
+        check_unfiltered(self, "Compacting")?;
+        let file_key = RootKey::new(&mut *self.reader(), self.begin())?;
+        let mut writer = RootFileWriter::new(output, &file_key.name, &file_key.title)?;
+        writer.compression = Compression::from_setting(self.compress());
+        writer.streamer_list = read_streamer_list(self)?;
+
+        for key in latest_cycles(self.keys()) {
+            let mut copy = key.clone();
+            copy.seek_pdir = BEGIN;
+            let copy = writer.copy_record(self, key, copy, None, false)?;
+            writer.keys.push(copy);
+        }
+        writer.close()
+    }
+}
+
+/// Rejects a file opened by `RootFile::with_filter`, whose keys are not all
+/// known, for `operation` to not drop the others.
+fn check_unfiltered<T: Read + Seek>(
+    file: &RootFile<T>,
+    operation: &str,
+) -> Result<(), RootIoError> {
+    if file.is_filtered() {
+        return Err(RootIoError::Unimplemented(format!(
+            "{} a file opened with a key filter",
+            operation
+        )));
+    }
+    Ok(())
+}
+
+/// A key header with the given names, to be placed by `append_record`.
+fn new_key(class_name: &str, name: &str, title: &str, cycle: u16) -> RootKey {
+    RootKey {
+        begin: 0,
+        meta_data: vec![],
+        obj_begin: 0,
+        nbytes: 0,
+        version: 0,
+        obj_len: 0,
+        datime: datime_now(),
+        key_len: 0,
+        cycle,
+        seek_key: 0,
+        seek_pdir: BEGIN,
+        class_name: class_name.to_string(),
+        name: name.to_string(),
+        title: title.to_string(),
+    }
+}
+
+/// The key list record of a directory holding `keys`.
+fn key_list(keys: &[RootKey]) -> Result<Vec<u8>, RootIoError> {
+    let mut list = vec![];
+    list.write_u32::<BigEndian>(keys.len() as u32)?;
+    for key in keys {
+        key.write_header(&mut list)?;
+    }
+    Ok(list)
+}
+
+/// Whether objects of `class_name` stream no other objects, and so hold no
+/// class tags.
+fn has_no_class_tags(class_name: &str) -> bool {
+    matches!(class_name, "TObject" | "TNamed" | "TObjString")
+        || class_name.starts_with("TParameter<")
+}
+
+fn is_directory(class_name: &str) -> bool {
+    class_name.starts_with("TDirectory")
+}
+
+/// Whether `hadd` would sum or chain objects of `class_name` of the same
+/// path, rather than keep the first.
+fn is_summed(class_name: &str) -> bool {
+    ["TH1", "TH2", "TH3", "TProfile"]
+        .iter()
+        .any(|prefix| class_name.starts_with(prefix))
+        || class_name == "TTree"
+        || class_name == "TNtuple"
+        || class_name == "TNtupleD"
+}
+
+/// The keys with the highest cycle of their name, in file order.
+fn latest_cycles(keys: &[RootKey]) -> impl Iterator<Item = &RootKey> {
+    let mut latest = HashMap::new();
+    for key in keys {
+        let cycle = latest.entry(key.name.as_str()).or_insert(key.cycle);
+        *cycle = key.cycle.max(*cycle);
+    }
+    keys.iter()
+        .filter(move |key| latest[key.name.as_str()] == key.cycle)
+}
+
+/// The decompressed `StreamerInfo` list of `file` and its key header length.
+fn read_streamer_list<T: Read + Seek>(
+    file: &RootFile<T>,
+) -> Result<Option<(Vec<u8>, u16)>, RootIoError> {
+    if file.seek_info() == 0 {
+        return Ok(None);
+    }
+    let key = RootKey::new(&mut *file.reader(), file.seek_info())?;
+    Ok(Some((file.read_object(&key)?, key.key_len)))
+}
+
+/// Streams a `TFree` record for the bytes `first..=last`.
+fn write_free_segment(writer: &mut impl Write, first: u64, last: u64) -> Result<(), RootIoError> {
+    let large = last > START_BIG_FILE;
+    writer.write_u16::<BigEndian>(if large {
+        FREE_VERSION + VER_THRESHOLD_KEY
+    } else {
+        FREE_VERSION
+    })?;
+    write_as_u64(large, writer, first)?;
+    write_as_u64(large, writer, last)?;
+    Ok(())
+}
+
+/// The current time in UTC, in the packed `TDatime` format.
+fn datime_now() -> u32 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since the epoch; see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+
+    let (hour, min, sec) = (rem / 3600, rem / 60 % 60, rem % 60);
+    (((year.max(1995) - 1995) << 26 | month << 22 | day << 17 | hour << 12 | min << 6 | sec)
+        & 0xffff_ffff) as u32
+}
+
+/// A random `TUUID` (version 1 layout, random contents).
+fn new_uuid() -> [u8; 18] {
+    let mut uuid = [0u8; 18];
+    uuid[1] = 1;
+    for chunk in uuid[2..].chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish().to_be_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+    uuid
+}
+
+#[cfg(test)]
+// Most of the tests compress with zlib.
+#[cfg_attr(not(feature = "zlib"), allow(unused_imports))]
+mod tests {
+    use super::{key_list, Compression, RootFileWriter, BEGIN, DIRECTORY_VERSION, MAX_BLOCK_LEN};
+    use crate::directory::tests::{cyclic_file, nested_file};
+    use crate::entry::HEADER_SIZE;
+    use crate::histogram::tests::th1f;
+    use crate::{
+        ElementType, RootDirectory, RootFile, RootKey, StreamKind, StreamerInfo, TObjString,
+    };
+    use std::io::Cursor;
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn write_and_read_back() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "test.root", "").unwrap();
+        writer.set_compression(Compression::Zlib(6));
+        assert_eq!(
+            writer
+                .write_object("TObjString", "a", "", b"short")
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            writer
+                .write_object("TObjString", "b", "", &[3; 1000])
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            writer
+                .write_object("TObjString", "a", "", b"again")
+                .unwrap(),
+            2
+        );
+        let index = TObjString {
+            string: "x".repeat(1000),
+        };
+        writer
+            .write_compressed("index", &index, Compression::None)
+            .unwrap();
+        let bytes = writer.close().unwrap().into_inner();
+
+        let root = RootFile::from_bytes(bytes).unwrap();
+        assert!(!root.is_large_file());
+        assert_eq!(root.compress(), 106);
+        assert!(root.directory().is_some());
+        assert!(root.seek_info() > 0);
+        assert_eq!(root.nfree(), 1);
+
+        let keys = root.keys();
+        let names: Vec<_> = keys.iter().map(|k| (k.name.as_str(), k.cycle)).collect();
+        assert_eq!(names, [("a", 1), ("b", 1), ("a", 2), ("index", 1)]);
+        assert!(keys[1].nbytes < keys[1].obj_len);
+        assert!(keys[1].compression_ratio() > 1.0);
+        assert_eq!(keys[3].compression_ratio(), 1.0);
+        assert_eq!(keys[3].stored_len(), keys[3].object_len());
+        assert_eq!(keys[0].parent_directory(), BEGIN);
+        assert!(keys[0].written_at().year >= 2020);
+        assert_eq!(
+            root.stream_kind(&keys[1]).unwrap(),
+            Some(StreamKind::ZlibNew)
+        );
+        assert_eq!(keys[3].nbytes, keys[3].obj_len + keys[3].key_len as u32);
+        assert_eq!(root.stream_kind(&keys[3]).unwrap(), None);
+        assert_eq!(root.read_object(&keys[0]).unwrap(), b"short");
+        assert_eq!(root.read_object(&keys[1]).unwrap(), vec![3; 1000]);
+        assert_eq!(root.read_object(&keys[2]).unwrap(), b"again");
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn split_large_objects() {
+        let payload: Vec<u8> = (0..MAX_BLOCK_LEN + 1000).map(|i| (i % 251) as u8).collect();
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "large.root", "").unwrap();
+        writer.set_compression(Compression::Zlib(1));
+        writer
+            .write_object("TObjString", "blob", "", &payload)
+            .unwrap();
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+
+        let key = &root.keys()[0];
+        let raw = root.read_raw_object(key).unwrap();
+        let first = u32::from_le_bytes([raw[3], raw[4], raw[5], 0]) as usize;
+        assert_eq!(&raw[HEADER_SIZE + first..][..2], b"ZL");
+        assert_eq!(root.read_object(key).unwrap(), payload);
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn update_existing_file() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "shared.root", "").unwrap();
+        writer.set_compression(Compression::Zlib(4));
+        writer
+            .write_object("TObjString", "a", "", b"first")
+            .unwrap();
+        writer.add_streamer_info(StreamerInfo::new("A", 1).with_member("x", ElementType::Int));
+        let file = writer.close().unwrap();
+
+        let mut writer = RootFileWriter::update(file).unwrap();
+        assert_eq!(writer.compression, Compression::Zlib(4));
+        assert_eq!(
+            writer
+                .write_object("TObjString", "a", "", b"second")
+                .unwrap(),
+            2
+        );
+        writer.add_streamer_info(StreamerInfo::new("B", 1).with_member("y", ElementType::Int));
+        let mut bytes = writer.close().unwrap().into_inner();
+
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+        let keys = root.keys();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(root.read_object(&keys[0]).unwrap(), b"first");
+        assert_eq!(root.read_object(&keys[1]).unwrap(), b"second");
+        // The old streamer info, key list and free list form one gap.
+        assert_eq!(root.nfree(), 2);
+        let key = RootKey::new(&mut Cursor::new(&bytes), root.seek_info()).unwrap();
+        let list = root.read_object(&key).unwrap();
+        let find = |needle: &[u8]| list.windows(needle.len()).position(|w| w == needle);
+        assert!(find(b"\x01A").unwrap() < find(b"\x01B").unwrap());
+
+        // Without the directory, the record scan skips the gap.
+        bytes[28..32].copy_from_slice(&[0; 4]);
+        let root = RootFile::from_bytes(bytes).unwrap();
+        assert!(root.directory().is_none());
+        assert!(root.keys().iter().any(|k| k.name == "a" && k.cycle == 2));
+    }
+
+    #[test]
+    fn delete_keys() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "purge.root", "").unwrap();
+        for payload in [&b"one"[..], b"two", b"three"] {
+            writer.write_object("TObjString", "a", "", payload).unwrap();
+        }
+        writer
+            .write_object("TObjString", "b", "", b"other")
+            .unwrap();
+        assert_eq!(writer.delete("a", Some(1)), 1);
+        let file = writer.close().unwrap();
+        let old_end = file.get_ref().len() as u64;
+
+        let mut writer = RootFileWriter::update(file).unwrap();
+        assert_eq!(writer.delete("b", None), 1);
+        assert_eq!(writer.delete("a", Some(1)), 0);
+        let mut bytes = writer.close().unwrap().into_inner();
+
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+        let names: Vec<_> = root
+            .keys()
+            .iter()
+            .map(|k| (k.name.as_str(), k.cycle))
+            .collect();
+        assert_eq!(names, [("a", 2), ("a", 3)]);
+        // `b` was at the end with the old records, which are not written
+        // over, so it is left as a gap along with the first cycle.
+        assert!(root.seek_info() >= old_end);
+        assert_eq!(root.nfree(), 3);
+
+        bytes[28..32].copy_from_slice(&[0; 4]);
+        let root = RootFile::from_bytes(bytes).unwrap();
+        let scanned: Vec<_> = root
+            .keys()
+            .iter()
+            .filter(|k| k.class_name == "TObjString")
+            .map(|k| (k.name.as_str(), k.cycle))
+            .collect();
+        assert_eq!(scanned, [("a", 2), ("a", 3)]);
+    }
+
+    #[test]
+    fn delete_subdirectories() {
+        let nested = RootFile::from_bytes(nested_file()).unwrap();
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "purge.root", "").unwrap();
+        writer.merge(&nested, None).unwrap();
+        assert_eq!(writer.delete("sub", None), 1);
+        let mut bytes = writer.close().unwrap().into_inner();
+
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+        let names: Vec<_> = root.keys().iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["h_pt", "sub_keys"]);
+        // No key list of `sub` is written, and its records are freed.
+        bytes[28..32].copy_from_slice(&[0; 4]);
+        let root = RootFile::from_bytes(bytes).unwrap();
+        assert!(root
+            .keys()
+            .iter()
+            .all(|k| !k.name.starts_with("sub") || k.name == "sub_keys"));
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn compact_file() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "old.root", "").unwrap();
+        writer.set_compression(Compression::Zlib(1));
+        writer
+            .write_object("TObjString", "a", "", &[1; 1000])
+            .unwrap();
+        writer
+            .write_object("TObjString", "a", "", &[2; 1000])
+            .unwrap();
+        writer.write_object("TObjString", "b", "", b"gone").unwrap();
+        writer.write_object("TObjString", "c", "", b"kept").unwrap();
+        writer.add_streamer_info(StreamerInfo::new("A", 1));
+        assert_eq!(writer.delete("b", None), 1);
+        let old = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+        assert_eq!(old.nfree(), 2);
+
+        let bytes = old.compact(Cursor::new(vec![])).unwrap().into_inner();
+        assert!((bytes.len() as u64) < old.end());
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(root.nfree(), 1);
+        assert_eq!(root.compress(), 101);
+        let names: Vec<_> = root
+            .keys()
+            .iter()
+            .map(|k| (k.name.as_str(), k.cycle))
+            .collect();
+        assert_eq!(names, [("a", 2), ("c", 1)]);
+        assert_eq!(root.read_object(&root.keys()[0]).unwrap(), vec![2; 1000]);
+        assert_eq!(root.read_object(&root.keys()[1]).unwrap(), b"kept");
+
+        let key = RootKey::new(&mut Cursor::new(&bytes), root.seek_info()).unwrap();
+        let list = root.read_object(&key).unwrap();
+        assert!(list.windows(2).any(|w| w == b"\x01A"));
+    }
+
+    #[test]
+    fn compact_subdirectories() {
+        let old = merge_input(
+            &[("TObjString", "a", b"a")],
+            &[("x", b"x"), ("y", b"y")],
+            "A",
+        );
+        let bytes = old.compact(Cursor::new(vec![])).unwrap().into_inner();
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+        let names: Vec<_> = root.keys().iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["sub", "a"]);
+        // The objects the filter dropped would be lost.
+        let filtered = RootFile::with_filter(Cursor::new(bytes), |key| key.name == "a").unwrap();
+        assert!(filtered.compact(Cursor::new(vec![])).is_err());
+        let mut writer = RootFileWriter::in_memory("merged.root").unwrap();
+        assert!(writer.merge(&filtered, None).is_err());
+        let cyclic = RootFile::from_bytes(cyclic_file()).unwrap();
+        assert!(cyclic.compact(Cursor::new(vec![])).is_err());
+        let children = root.directory_keys(&root["sub"]).unwrap();
+        let names: Vec<_> = children.iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(root.read_object(&children[1]).unwrap(), b"y");
+    }
+
+    /// A file with `objects` at the top and `children` in a subdirectory
+    /// `sub`, along with the streamer info of `class_name`.
+    fn merge_input(
+        objects: &[(&str, &str, &[u8])],
+        children: &[(&str, &[u8])],
+        class_name: &str,
+    ) -> RootFile<Cursor<Vec<u8>>> {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "in.root", "").unwrap();
+        for (name, payload) in children {
+            writer
+                .write_object("TObjString", name, "", payload)
+                .unwrap();
+        }
+        let list = key_list(writer.keys()).unwrap();
+        writer
+            .write_object("KeysList", "sub_keys", "", &list)
+            .unwrap();
+        let dir = RootDirectory {
+            version: DIRECTORY_VERSION,
+            ctime: 0,
+            mtime: 0,
+            nbytes_keys: list.len() as u32,
+            nbytes_name: 0,
+            seek_dir: 0,
+            seek_parent: BEGIN,
+            seek_keys: writer.keys().last().unwrap().seek_key,
+            uuid: [0; 18],
+        };
+        let mut record = vec![];
+        dir.write(&mut record).unwrap();
+        writer
+            .write_object("TDirectoryFile", "sub", "", &record)
+            .unwrap();
+        for (name, _) in children {
+            writer.delete(name, None);
+        }
+        writer.delete("sub_keys", None);
+        for (class_name, name, payload) in objects {
+            writer.write_object(class_name, name, "", payload).unwrap();
+        }
+        writer.add_streamer_info(StreamerInfo::new(class_name, 1));
+        RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn merge_files() {
+        let (h1, h2) = (
+            th1f(&[0.0, 1.0, 2.0, 0.0], 3.0, &[]),
+            th1f(&[0.0, 2.0, 0.5, 1.0], 4.0, &[]),
+        );
+        let first = merge_input(
+            &[
+                ("TObjString", "a", b"first"),
+                ("TH1F", "h", &h1),
+                ("THashList", "l", b"first"),
+                ("TTree", "t", b"first"),
+            ],
+            &[("x", b"first")],
+            "A",
+        );
+        let second = merge_input(
+            &[
+                ("TObjString", "a", b"second"),
+                ("TObjString", "b", b"b"),
+                ("TH1F", "h", &h2),
+                ("THashList", "l", b"second"),
+            ],
+            &[("x", b"second"), ("y", b"second")],
+            "B",
+        );
+        // The tree cannot be chained, so nothing of this input is merged.
+        let third = merge_input(
+            &[("TObjString", "c", b"c"), ("TTree", "t", b"third")],
+            &[("z", b"third")],
+            "C",
+        );
+
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "merged.root", "").unwrap();
+        writer.merge(&first, None).unwrap();
+        writer.merge(&second, None).unwrap();
+        let end = writer.end;
+        assert!(writer.merge(&third, None).is_err());
+        assert_eq!(writer.end, end);
+        let bytes = writer.close().unwrap().into_inner();
+
+        let root = RootFile::from_bytes(bytes.clone()).unwrap();
+        let names: Vec<_> = root.keys().iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["sub", "a", "h", "l", "t", "b"]);
+        assert_eq!(root.read_object(&root["a"]).unwrap(), b"first");
+        assert_eq!(root.read_object(&root["l"]).unwrap(), b"first");
+        assert_eq!(
+            root.read_object(&root["h"]).unwrap(),
+            th1f(&[0.0, 3.0, 2.5, 1.0], 7.0, &[])
+        );
+        let sub = root.directory_keys(&root["sub"]).unwrap();
+        let children: Vec<_> = sub.iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(children, ["x", "y"]);
+        assert_eq!(root.read_object(&sub[0]).unwrap(), b"first");
+        assert_eq!(root.read_object(&sub[1]).unwrap(), b"second");
+        assert!(sub.iter().all(|k| k.seek_pdir == root["sub"].seek_key));
+
+        let classes: Vec<_> = root
+            .streamer_infos()
+            .unwrap()
+            .into_iter()
+            .map(|info| info.class_name)
+            .collect();
+        assert_eq!(classes, ["A", "B"]);
+    }
+
+    #[test]
+    fn merge_unknown_streamer_elements() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "in.root", "").unwrap();
+        writer.add_streamer_info(StreamerInfo::new("A", 1).with_member("fObj", ElementType::Int));
+        let mut bytes = writer.close().unwrap().into_inner();
+        // An element of a class this crate reads but does not write.
+        let at = bytes
+            .windows(18)
+            .position(|w| w == b"TStreamerBasicType")
+            .unwrap();
+        bytes[at..at + 18].copy_from_slice(b"TStreamerObjectAny");
+        let input = RootFile::from_bytes(bytes).unwrap();
+        let infos = input.streamer_infos().unwrap();
+        assert!(matches!(
+            infos[0].elements[0].element_type,
+            ElementType::Other { .. }
+        ));
+
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "merged.root", "").unwrap();
+        writer.merge(&merge_input(&[], &[], "B"), None).unwrap();
+        writer.merge(&input, None).unwrap();
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+        assert_eq!(root.streamer_infos().unwrap()[1], infos[0]);
+    }
+
+    #[cfg(feature = "zlib")]
+    #[test]
+    fn copy_objects() {
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "src.root", "").unwrap();
+        writer
+            .write_object("TObjString", "raw", "", &[7; 1000])
+            .unwrap();
+        writer
+            .write_object("TObjString", "raw", "", &[8; 1000])
+            .unwrap();
+        writer
+            .write_object("TList", "list", "", &[0xff; 8])
+            .unwrap();
+        let src = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+
+        let mut writer = RootFileWriter::new(Cursor::new(vec![]), "dst.root", "").unwrap();
+        assert_eq!(writer.copy_object(&src, "raw", "latest", None).unwrap(), 1);
+        let zlib = Some(Compression::Zlib(9));
+        assert_eq!(writer.copy_object(&src, "raw;1", "first", zlib).unwrap(), 1);
+        assert_eq!(writer.copy_object(&src, "raw;1", "first", None).unwrap(), 2);
+        assert!(writer.copy_object(&src, "raw;3", "x", None).is_err());
+        // A longer name would shift the class tags of the list.
+        assert!(writer.copy_object(&src, "list", "renamed", None).is_err());
+        assert_eq!(writer.copy_object(&src, "list", "list", None).unwrap(), 1);
+        let root = RootFile::from_bytes(writer.close().unwrap().into_inner()).unwrap();
+
+        let keys = root.keys();
+        assert_eq!(keys[0].nbytes - keys[0].key_len as u32, 1000);
+        assert!(keys[1].nbytes < keys[1].obj_len);
+        assert_eq!(root.read_object(&keys[0]).unwrap(), vec![8; 1000]);
+        assert_eq!(root.read_object(&keys[1]).unwrap(), vec![7; 1000]);
+        assert_eq!(root.read_object(&keys[2]).unwrap(), vec![7; 1000]);
+    }
+}